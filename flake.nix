@@ -48,6 +48,14 @@
           config-dir
           worktree-merge
           worktree-discard
+          worktree-carry
+          worktree-uncommitted
+          worktree-dirty
+          worktree-actions
+          worktree-restore
+          overlay-merge
+          overlay-discard
+          overlay-conflict
           ssh-agent-allow
           ssh-agent-deny
           claude-binary
@@ -60,11 +68,33 @@
           path-readonly
           dangerous-files-deny
           dangerous-files-allow
+          dangerous-files-patterns
+          dangerous-files-missing-report
+          dangerous-files-missing-block
           pid-namespace
           unix-sockets-allow
           unix-sockets-deny
+          unix-sockets-compat-deny
+          unix-sockets-compat-allow
           unix-sockets-aarch64-allow
           unix-sockets-aarch64-deny
+          network-host
+          network-none
+          network-proxy
+          user-mounts
+          env-policy-deny
+          env-policy-clean
+          landlock
+          seccomp-rules
+          seccomp-profile
+          seccomp-notify
+          terminal-injection
+          pty
+          record
+          session-lifecycle
+          sessions
+          seccomp-hardening-deny
+          seccomp-hardening-disabled
           ;
       };
 
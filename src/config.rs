@@ -2,9 +2,12 @@ use std::env;
 use std::fs;
 use std::path::PathBuf;
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-use crate::Cli;
+use crate::proxy::DEFAULT_PROXY_ALLOW;
+use crate::seccomp;
+use crate::util::expand_path;
+use crate::{CarryChanges, Cli, DirtyCheckout, MergeUncommitted, NetworkMode};
 
 #[derive(Debug, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
@@ -20,11 +23,89 @@ pub struct Config {
     #[serde(default)]
     pub allow_dangerous_writes: bool,
     #[serde(default)]
+    pub block_dangerous_creation: bool,
+    #[serde(default)]
+    pub extra_dangerous_paths: Vec<String>,
+    #[serde(default)]
+    pub ignore_dangerous_paths: Vec<String>,
+    #[serde(default)]
+    pub landlock: bool,
+    #[serde(default)]
     pub allow_unix_sockets: bool,
     #[serde(default)]
+    pub allow_compat_syscalls: bool,
+    #[serde(default)]
+    pub allow_io_uring: bool,
+    #[serde(default)]
+    pub disable_seccomp_hardening: bool,
+    #[serde(default)]
+    pub seccomp_profile: Option<String>,
+    #[serde(default)]
+    pub seccomp_rules: Vec<seccomp::Rule>,
+    #[serde(default)]
+    pub seccomp_notify: bool,
+    #[serde(default)]
+    pub unix_socket_allow: Vec<String>,
+    #[serde(default)]
     pub worktree: bool,
     #[serde(default)]
+    pub carry_changes: Option<CarryChanges>,
+    #[serde(default)]
+    pub merge_uncommitted: Option<MergeUncommitted>,
+    #[serde(default)]
+    pub dirty_checkout: Option<DirtyCheckout>,
+    #[serde(default)]
+    pub discard_retention_days: Option<u64>,
+    #[serde(default)]
+    pub overlay: bool,
+    #[serde(default)]
+    pub network: Option<NetworkMode>,
+    #[serde(default)]
+    pub proxy_allow: Option<Vec<String>>,
+    #[serde(default)]
+    pub clean_env: bool,
+    #[serde(default)]
+    pub env: Vec<String>,
+    #[serde(default)]
+    pub env_deny: Vec<String>,
+    #[serde(default)]
+    pub bind: Vec<String>,
+    #[serde(default)]
+    pub ro_bind: Vec<String>,
+    #[serde(default)]
+    pub tmpfs: Vec<String>,
+    #[serde(default)]
+    pub hide: Vec<String>,
+    #[serde(default)]
     pub command: Option<String>,
+    #[serde(default)]
+    pub record_sessions: bool,
+}
+
+/// An extra bind mount requested via `--bind`/`--ro-bind` or the config file.
+#[derive(Serialize)]
+pub struct BindMount {
+    pub src: PathBuf,
+    pub dst: PathBuf,
+    pub readonly: bool,
+}
+
+impl BindMount {
+    /// Parse `SRC[:DST]`. Without `DST`, the source is mounted at the same path.
+    fn parse(spec: &str, readonly: bool) -> Self {
+        let (src, dst) = match spec.split_once(':') {
+            Some((src, dst)) => (expand_path(src), expand_path(dst)),
+            None => (expand_path(spec), expand_path(spec)),
+        };
+        BindMount { src, dst, readonly }
+    }
+}
+
+/// ajail's directory under `$XDG_STATE_HOME` (default `~/.local/state`).
+pub fn state_dir() -> PathBuf {
+    let xdg_state = env::var("XDG_STATE_HOME")
+        .unwrap_or_else(|_| format!("{}/.local/state", env::var("HOME").unwrap_or_default()));
+    PathBuf::from(xdg_state).join("ajail")
 }
 
 fn config_path() -> PathBuf {
@@ -59,15 +140,66 @@ pub fn load_config() -> Config {
 }
 
 /// Merged CLI flags (override) with config file (defaults).
+///
+/// Serialized into the header of session recordings.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Options {
     pub allow_ssh_agent: bool,
     pub allow_gpg_agent: bool,
     pub allow_xdg_runtime: bool,
     pub allow_docker: bool,
     pub allow_dangerous_writes: bool,
+    pub block_dangerous_creation: bool,
+    /// Glob patterns protected in addition to the built-in dangerous paths.
+    pub extra_dangerous_paths: Vec<String>,
+    /// Built-in or extra patterns to leave writable.
+    pub ignore_dangerous_paths: Vec<String>,
+    pub landlock: bool,
     pub allow_unix_sockets: bool,
+    pub allow_compat_syscalls: bool,
+    /// io_uring operations bypass seccomp, including the Unix socket block
+    pub allow_io_uring: bool,
+    pub disable_seccomp_hardening: bool,
+    /// OCI/Docker seccomp profile installed next to the built-in filter
+    pub seccomp_profile: Option<PathBuf>,
+    /// Extra seccomp rules, checked before the built-in ones
+    pub seccomp_rules: Vec<seccomp::Rule>,
+    /// Broker Unix socket connections in the parent instead of blocking them
+    pub seccomp_notify: bool,
+    /// Socket path patterns the broker allows without asking
+    pub unix_socket_allow: Vec<String>,
     pub worktree: bool,
+    /// Uncommitted changes copied into the worktree
+    pub carry_changes: CarryChanges,
+    /// What merging does with uncommitted changes in the worktree
+    pub merge_uncommitted: MergeUncommitted,
+    /// What merging does when the original checkout has uncommitted changes
+    pub dirty_checkout: DirtyCheckout,
+    /// Name for the branch kept by the `branch` worktree action
+    pub branch_name: Option<String>,
+    /// Where the `patch` worktree action writes
+    pub patch_output: Option<PathBuf>,
+    /// Days discarded worktree sessions are kept under `refs/ajail/discarded`
+    pub discard_retention_days: u64,
+    pub overlay: bool,
+    pub network: NetworkMode,
+    pub proxy_allow: Vec<String>,
+    pub clean_env: bool,
+    /// `KEY` to forward or `KEY=VALUE` to set inside the sandbox. Left out
+    /// of recordings, as the values may be secrets.
+    #[serde(skip)]
+    pub env: Vec<String>,
+    /// Variable name patterns stripped in addition to the default denylist
+    pub env_deny: Vec<String>,
+    pub bind_mounts: Vec<BindMount>,
+    pub tmpfs_mounts: Vec<PathBuf>,
+    pub hidden_paths: Vec<PathBuf>,
     pub command: String,
+    /// Asciicast file to record the terminal session to
+    pub record: Option<PathBuf>,
+    /// Record every session into the state directory
+    pub record_sessions: bool,
 }
 
 pub fn merge_options(cli: &Cli, config: &Config) -> Options {
@@ -77,12 +209,91 @@ pub fn merge_options(cli: &Cli, config: &Config) -> Options {
         allow_xdg_runtime: cli.allow_xdg_runtime || config.allow_xdg_runtime,
         allow_docker: cli.allow_docker || config.allow_docker,
         allow_dangerous_writes: cli.allow_dangerous_writes || config.allow_dangerous_writes,
+        block_dangerous_creation: cli.block_dangerous_creation || config.block_dangerous_creation,
+        extra_dangerous_paths: config.extra_dangerous_paths.clone(),
+        ignore_dangerous_paths: config.ignore_dangerous_paths.clone(),
+        landlock: cli.landlock || config.landlock,
         allow_unix_sockets: cli.allow_unix_sockets || config.allow_unix_sockets,
+        allow_compat_syscalls: cli.allow_compat_syscalls || config.allow_compat_syscalls,
+        allow_io_uring: cli.allow_io_uring || config.allow_io_uring,
+        disable_seccomp_hardening: cli.disable_seccomp_hardening
+            || config.disable_seccomp_hardening,
+        seccomp_profile: cli
+            .seccomp_profile
+            .as_ref()
+            .map(|p| expand_path(&p.to_string_lossy()))
+            .or_else(|| config.seccomp_profile.as_deref().map(expand_path)),
+        seccomp_rules: config.seccomp_rules.clone(),
+        seccomp_notify: cli.seccomp_notify || config.seccomp_notify,
+        unix_socket_allow: config
+            .unix_socket_allow
+            .iter()
+            .chain(&cli.unix_socket_allow)
+            .cloned()
+            .collect(),
         worktree: cli.worktree || config.worktree,
+        carry_changes: cli
+            .carry_changes
+            .or(config.carry_changes)
+            .unwrap_or_default(),
+        merge_uncommitted: cli
+            .merge_uncommitted
+            .or(config.merge_uncommitted)
+            .unwrap_or_default(),
+        dirty_checkout: cli
+            .dirty_checkout
+            .or(config.dirty_checkout)
+            .unwrap_or_default(),
+        branch_name: cli.branch_name.clone(),
+        patch_output: cli.patch_output.clone(),
+        discard_retention_days: cli
+            .discard_retention_days
+            .or(config.discard_retention_days)
+            .unwrap_or(30),
+        overlay: cli.overlay || config.overlay,
+        network: cli.network.or(config.network).unwrap_or_default(),
+        proxy_allow: config
+            .proxy_allow
+            .clone()
+            .unwrap_or_else(|| DEFAULT_PROXY_ALLOW.iter().map(|h| h.to_string()).collect())
+            .into_iter()
+            .chain(cli.proxy_allow.iter().cloned())
+            .collect(),
+        clean_env: cli.clean_env || config.clean_env,
+        env: config.env.iter().chain(&cli.env).cloned().collect(),
+        env_deny: config.env_deny.clone(),
+        bind_mounts: config
+            .bind
+            .iter()
+            .chain(&cli.bind)
+            .map(|spec| BindMount::parse(spec, false))
+            .chain(
+                config
+                    .ro_bind
+                    .iter()
+                    .chain(&cli.ro_bind)
+                    .map(|spec| BindMount::parse(spec, true)),
+            )
+            .collect(),
+        tmpfs_mounts: expand_paths(&config.tmpfs, &cli.tmpfs),
+        hidden_paths: expand_paths(&config.hide, &cli.hide),
         command: cli
             .command
             .clone()
             .or_else(|| config.command.clone())
             .unwrap_or_else(|| "claude".to_string()),
+        record: cli
+            .record
+            .as_ref()
+            .map(|p| expand_path(&p.to_string_lossy())),
+        record_sessions: config.record_sessions,
     }
 }
+
+fn expand_paths(from_config: &[String], from_cli: &[PathBuf]) -> Vec<PathBuf> {
+    from_config
+        .iter()
+        .map(|p| expand_path(p))
+        .chain(from_cli.iter().map(|p| expand_path(&p.to_string_lossy())))
+        .collect()
+}
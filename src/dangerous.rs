@@ -0,0 +1,197 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Options;
+use crate::glob;
+use crate::overlay;
+
+/// Known dangerous files that should be read-only inside the sandbox.
+/// Entries here and in `DANGEROUS_DIRS` are glob patterns relative to the repo root.
+const DANGEROUS_FILES: &[&str] = &[
+    ".bashrc",
+    ".zshrc",
+    ".bash_profile",
+    ".zprofile",
+    ".profile",
+    ".gitconfig",
+    ".gitmodules",
+    ".ripgreprc",
+    ".mcp.json",
+    ".git/config",
+];
+
+/// Known dangerous directories that should be read-only inside the sandbox.
+const DANGEROUS_DIRS: &[&str] = &[
+    ".git/hooks",
+    ".vscode",
+    ".idea",
+    ".zed",
+    ".claude/commands",
+    ".claude/agents",
+];
+
+/// Dangerous path patterns in effect: the built-in lists plus
+/// `extra_dangerous_paths`, minus anything in `ignore_dangerous_paths`.
+fn dangerous_patterns(options: &Options) -> Vec<&str> {
+    DANGEROUS_FILES
+        .iter()
+        .chain(DANGEROUS_DIRS)
+        .copied()
+        .chain(options.extra_dangerous_paths.iter().map(String::as_str))
+        .filter(|p| !options.ignore_dangerous_paths.iter().any(|i| i == p))
+        .collect()
+}
+
+/// The directory `pattern` is relative to, and the pattern relative to it.
+///
+/// In a worktree `.git` is a file, and the hooks and config live in the
+/// original repo's git dir, so `.git/` patterns are resolved against
+/// `git_dir` there, or skipped without one.
+fn resolve<'a>(
+    repo: &'a Path,
+    git_dir: Option<&'a Path>,
+    pattern: &'a str,
+) -> Option<(&'a Path, &'a str)> {
+    let Some(rest) = pattern.strip_prefix(".git/") else {
+        return Some((repo, pattern));
+    };
+    match repo.join(".git").symlink_metadata() {
+        Ok(meta) if !meta.is_dir() => git_dir.map(|dir| (dir, rest)),
+        _ => Some((repo, pattern)),
+    }
+}
+
+/// Existing paths under `repo` (or `git_dir`, see `resolve`) matching the
+/// dangerous patterns in effect.
+pub fn find_dangerous_paths(
+    repo: &Path,
+    git_dir: Option<&Path>,
+    options: &Options,
+) -> BTreeSet<PathBuf> {
+    dangerous_patterns(options)
+        .into_iter()
+        .filter_map(|pattern| resolve(repo, git_dir, pattern))
+        .flat_map(|(root, pattern)| glob::expand(root, pattern))
+        .filter(|path| path.exists())
+        .collect()
+}
+
+/// Report dangerous paths that exist now but were not in `before`.
+/// Called after the session so the user can review them before host
+/// tooling (shells, git, editors) picks them up. In an overlay's upper
+/// layer, whiteouts are reported as deletions.
+pub fn report_new_dangerous_paths(
+    repo: &Path,
+    git_dir: Option<&Path>,
+    options: &Options,
+    before: &BTreeSet<PathBuf>,
+) {
+    let (deleted, created): (Vec<PathBuf>, Vec<PathBuf>) =
+        find_dangerous_paths(repo, git_dir, options)
+            .into_iter()
+            .filter(|p| !before.contains(p))
+            .partition(|p| p.symlink_metadata().is_ok_and(|m| overlay::is_whiteout(&m)));
+    if !created.is_empty() {
+        eprintln!("ajail: warning: the session created dangerous paths, review them before use:");
+        for path in created {
+            eprintln!("  {}", path.display());
+        }
+    }
+    if !deleted.is_empty() {
+        eprintln!("ajail: the session deleted dangerous paths:");
+        for path in deleted {
+            eprintln!("  {}", path.display());
+        }
+    }
+}
+
+/// Host directories created so that missing dangerous paths can be masked
+/// inside the sandbox, in creation order.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Placeholders {
+    created: Vec<PathBuf>,
+    /// The placeholders standing in for dangerous paths, as opposed to
+    /// missing parents created along the way
+    #[serde(default)]
+    masked: Vec<PathBuf>,
+}
+
+/// Create empty directories for literal dangerous patterns that do not
+/// exist yet, as mount points for `protect_dangerous_files` to mask, so the
+/// sandbox cannot create them. Glob patterns cannot be pre-created and are
+/// only covered by `report_new_dangerous_paths`.
+///
+/// Placeholders are directories even for files: git does not list empty
+/// directories, and host tools do not find a file with content they cannot
+/// parse, such as an empty `.mcp.json`.
+pub fn create_placeholders(repo: &Path, git_dir: Option<&Path>, options: &Options) -> Placeholders {
+    let mut created = Vec::new();
+    let mut masked = Vec::new();
+
+    for (root, pattern) in dangerous_patterns(options)
+        .into_iter()
+        .filter_map(|pattern| resolve(repo, git_dir, pattern))
+    {
+        let rel = pattern
+            .strip_suffix("/**")
+            .or_else(|| pattern.strip_suffix('/'))
+            .unwrap_or(pattern);
+        if rel.contains(['*', '?']) {
+            continue;
+        }
+
+        let path = root.join(rel);
+        if path.symlink_metadata().is_ok() {
+            continue;
+        }
+
+        // Create missing parents one at a time so they can be removed later.
+        let mut missing: Vec<PathBuf> = path
+            .ancestors()
+            .skip(1)
+            .take_while(|p| p.starts_with(root) && p.symlink_metadata().is_err())
+            .map(Path::to_path_buf)
+            .collect();
+        missing.reverse();
+        let parents_ok = missing.iter().all(|dir| {
+            let ok = fs::create_dir(dir).is_ok();
+            if ok {
+                created.push(dir.clone());
+            }
+            ok
+        });
+        if !parents_ok {
+            continue;
+        }
+
+        match fs::create_dir(&path) {
+            Ok(()) => {
+                created.push(path.clone());
+                masked.push(path);
+            }
+            Err(e) => eprintln!(
+                "ajail: failed to create placeholder {}, it may be created by the sandbox: {e}",
+                path.display()
+            ),
+        }
+    }
+
+    Placeholders { created, masked }
+}
+
+impl Placeholders {
+    /// The placeholders to mask inside the sandbox.
+    pub fn masked(&self) -> &[PathBuf] {
+        &self.masked
+    }
+
+    /// Remove placeholders that are still empty, deepest first.
+    pub fn remove(&self) {
+        for path in self.created.iter().rev() {
+            fs::remove_dir(path).ok();
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use std::env;
+
+use crate::config::Options;
+use crate::glob;
+
+/// Variables stripped from the sandbox environment by default. Patterns
+/// match case-insensitively; `*` matches any run of characters.
+const DEFAULT_ENV_DENY: &[&str] = &[
+    "AWS_ACCESS_KEY_ID",
+    "AWS_SECRET_ACCESS_KEY",
+    "AWS_SESSION_TOKEN",
+    "AZURE_CLIENT_SECRET",
+    "GOOGLE_APPLICATION_CREDENTIALS",
+    "DOCKER_AUTH_CONFIG",
+    "*_TOKEN",
+    "*_API_KEY",
+    "*_APIKEY",
+    "*_ACCESS_KEY",
+    "*_SECRET*",
+    "*_PASSWORD",
+    "*_PASSWD",
+    "*_CREDENTIALS",
+    "*_PRIVATE_KEY",
+];
+
+/// Credentials the sandboxed agent itself needs. Kept even when they match
+/// the denylist.
+const DEFAULT_ENV_KEEP: &[&str] = &[
+    "ANTHROPIC_API_KEY",
+    "ANTHROPIC_AUTH_TOKEN",
+    "CLAUDE_CODE_OAUTH_TOKEN",
+];
+
+/// Variables forwarded in `--clean-env` mode without an explicit `--env`.
+const CLEAN_ENV_BASE: &[&str] = &[
+    "PATH",
+    "HOME",
+    "USER",
+    "LOGNAME",
+    "SHELL",
+    "TERM",
+    "COLORTERM",
+    "LANG",
+    "LANGUAGE",
+    "LC_*",
+    "TZ",
+    "SSL_CERT_FILE",
+    "SSL_CERT_DIR",
+    "NIX_SSL_CERT_FILE",
+    "XDG_RUNTIME_DIR",
+    // Agent configuration (model, base URL, feature flags)
+    "ANTHROPIC_*",
+    "CLAUDE_*",
+];
+
+fn matches_any(patterns: &[&str], name: &str) -> bool {
+    let name = name.to_ascii_uppercase();
+    patterns
+        .iter()
+        .any(|p| glob::matches(&p.to_ascii_uppercase(), &name))
+}
+
+/// Apply the environment policy to the current process environment:
+/// strip denied (or, with `clean_env`, all non-base) variables, then set
+/// `--env KEY=VALUE` overrides. Returns the names of stripped variables.
+///
+/// Must only be called in the forked child, which is single-threaded.
+pub fn apply_env_policy(options: &Options) -> Vec<String> {
+    let keep: Vec<&str> = options
+        .env
+        .iter()
+        .map(|spec| spec.split_once('=').map_or(spec.as_str(), |(k, _)| k))
+        .chain(DEFAULT_ENV_KEEP.iter().copied())
+        .chain(options.allow_ssh_agent.then_some("SSH_AUTH_SOCK"))
+        .collect();
+    let deny: Vec<&str> = DEFAULT_ENV_DENY
+        .iter()
+        .copied()
+        .chain(options.env_deny.iter().map(String::as_str))
+        .collect();
+
+    let mut stripped: Vec<String> = env::vars_os()
+        .filter_map(|(k, _)| k.into_string().ok())
+        .filter(|name| {
+            if keep.contains(&name.as_str()) {
+                return false;
+            }
+            if options.clean_env {
+                !matches_any(CLEAN_ENV_BASE, name)
+            } else {
+                matches_any(&deny, name)
+            }
+        })
+        .collect();
+    stripped.sort();
+
+    // Safety: we're in a forked child process, single-threaded.
+    for name in &stripped {
+        unsafe { env::remove_var(name) };
+    }
+    for spec in &options.env {
+        if let Some((key, value)) = spec.split_once('=') {
+            unsafe { env::set_var(key, value) };
+        }
+    }
+
+    stripped
+}
+
+/// Print which variables were not forwarded into the sandbox.
+pub fn report_stripped(stripped: &[String]) {
+    if stripped.is_empty() {
+        return;
+    }
+    eprintln!(
+        "ajail: not forwarding environment variables (use --env NAME to pass one through): {}",
+        stripped.join(", ")
+    );
+}
@@ -0,0 +1,116 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Expand a glob `pattern` relative to `root` into the existing paths it matches.
+///
+/// Supported syntax, per path component:
+/// - `*` matches any run of characters except `/`
+/// - `?` matches a single character
+/// - `**` matches zero or more directories
+///
+/// A trailing `**` matches the directory itself rather than every file below
+/// it, since protecting a directory covers its whole subtree. `**` never
+/// descends into `.git` or through symlinks.
+pub fn expand(root: &Path, pattern: &str) -> Vec<PathBuf> {
+    let mut components: Vec<&str> = pattern
+        .split('/')
+        .filter(|c| !c.is_empty() && *c != ".")
+        .collect();
+    while components.len() > 1 && components.last() == Some(&"**") {
+        components.pop();
+    }
+    if components == ["**"] {
+        return vec![root.to_path_buf()];
+    }
+
+    let mut out = Vec::new();
+    expand_into(root, &components, &mut out);
+    out.sort();
+    out.dedup();
+    out
+}
+
+fn expand_into(dir: &Path, components: &[&str], out: &mut Vec<PathBuf>) {
+    let Some((&first, rest)) = components.split_first() else {
+        out.push(dir.to_path_buf());
+        return;
+    };
+
+    if first == "**" {
+        // Zero directories...
+        expand_into(dir, rest, out);
+        // ...or one more, keeping `**` in front.
+        for entry in read_dir_sorted(dir) {
+            let is_real_dir = entry
+                .symlink_metadata()
+                .is_ok_and(|m| m.file_type().is_dir());
+            if is_real_dir && entry.file_name().is_some_and(|n| n != ".git") {
+                expand_into(&entry, components, out);
+            }
+        }
+    } else if has_wildcard(first) {
+        for entry in read_dir_sorted(dir) {
+            if entry
+                .file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| matches(first, n))
+            {
+                expand_into(&entry, rest, out);
+            }
+        }
+    } else {
+        let path = dir.join(first);
+        if path.symlink_metadata().is_ok() {
+            expand_into(&path, rest, out);
+        }
+    }
+}
+
+fn read_dir_sorted(dir: &Path) -> Vec<PathBuf> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|rd| rd.flatten().map(|e| e.path()).collect())
+        .unwrap_or_default();
+    entries.sort();
+    entries
+}
+
+fn has_wildcard(component: &str) -> bool {
+    component.contains(['*', '?'])
+}
+
+/// Match a single path component (or any other name) against a pattern
+/// with `*` and `?`.
+pub fn matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Position of the last `*` in the pattern, and the name position it matched up to.
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some('?') => {
+                p += 1;
+                n += 1;
+            }
+            Some(&c) if c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    n = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
@@ -0,0 +1,226 @@
+use std::fs::File;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use nix::errno::Errno;
+use nix::sys::signal::{SigSet, SigmaskHow, Signal, kill, sigprocmask};
+use nix::sys::wait::{WaitPidFlag, WaitStatus, waitpid};
+use nix::unistd::{ForkResult, Pid, dup2, fork, pipe, read};
+
+use crate::landlock;
+use crate::seccomp;
+
+/// Signals that would end ajail. The parent passes them on to the sandbox
+/// instead, so it always gets to clean up after the session.
+const TERMINATING: [Signal; 6] = [
+    Signal::SIGHUP,
+    Signal::SIGINT,
+    Signal::SIGQUIT,
+    Signal::SIGTERM,
+    Signal::SIGUSR1,
+    Signal::SIGUSR2,
+];
+
+/// Signals init also passes on to the command. SIGCHLD is waited for too,
+/// but only to reap.
+const FORWARDED: [Signal; 9] = [
+    TERMINATING[0],
+    TERMINATING[1],
+    TERMINATING[2],
+    TERMINATING[3],
+    TERMINATING[4],
+    TERMINATING[5],
+    Signal::SIGWINCH,
+    Signal::SIGCONT,
+    Signal::SIGTSTP,
+];
+
+/// Set once the parent got SIGHUP: the terminal is gone.
+static HUNG_UP: AtomicBool = AtomicBool::new(false);
+
+/// Fork, returning in the child. The parent stays behind as a minimal init,
+/// like tini: it forwards signals to the child, reaps every process that
+/// ends up under it, and exits with the child's status.
+///
+/// As PID 1 of a namespace, a process only gets signals it handles, so
+/// without this `SIGTERM` from outside would be dropped and orphans would
+/// stay zombies.
+///
+/// The init shares the sandbox's uid, so it is locked down: it is not
+/// dumpable, which keeps the command from tracing it or writing its memory
+/// through /proc/1/mem or `process_vm_writev`, and it drops all filesystem
+/// access and every syscall the loop does not need.
+pub fn fork_supervised() -> nix::Result<()> {
+    let mut signals = SigSet::empty();
+    for signal in FORWARDED {
+        signals.add(signal);
+    }
+    signals.add(Signal::SIGCHLD);
+    // Blocked before the fork, so no signal is lost before the loop starts.
+    let mut original = SigSet::empty();
+    sigprocmask(SigmaskHow::SIG_BLOCK, Some(&signals), Some(&mut original))?;
+    // Also before the fork, so the child cannot get at the init before it
+    // is locked down. The child waits for the rest until `locked` closes.
+    set_dumpable(false)?;
+    let (wait_locked, locked) = pipe()?;
+
+    match unsafe { fork() } {
+        Ok(ForkResult::Parent { child }) => {
+            drop(wait_locked);
+            if let Err(e) = lock_down() {
+                eprintln!("ajail: failed to lock down the sandbox init: {e}");
+                let _ = kill(child, Signal::SIGKILL);
+                std::process::exit(1);
+            }
+            drop(locked);
+            supervise(child, &signals)
+        }
+        Ok(ForkResult::Child) => {
+            die_with_parent();
+            drop(locked);
+            while let Err(Errno::EINTR) = read(wait_locked.as_raw_fd(), &mut [0]) {}
+            drop(wait_locked);
+            set_dumpable(true)?;
+            sigprocmask(SigmaskHow::SIG_SETMASK, Some(&original), None)?;
+            Ok(())
+        }
+        Err(e) => {
+            set_dumpable(true)?;
+            sigprocmask(SigmaskHow::SIG_SETMASK, Some(&original), None)?;
+            Err(e)
+        }
+    }
+}
+
+fn set_dumpable(dumpable: bool) -> nix::Result<()> {
+    let ret = unsafe {
+        libc::prctl(
+            libc::PR_SET_DUMPABLE,
+            libc::c_ulong::from(dumpable),
+            0,
+            0,
+            0,
+        )
+    };
+    Errno::result(ret).map(drop)
+}
+
+/// Confine the init to its supervision loop: no filesystem access, and only
+/// the syscalls in `seccomp::init_policy`.
+fn lock_down() -> io::Result<()> {
+    landlock::deny_filesystem()?;
+    seccomp::init_policy().install()
+}
+
+fn supervise(child: Pid, signals: &SigSet) -> ! {
+    loop {
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        let signo = unsafe { libc::sigwaitinfo(signals.as_ref(), &mut info) };
+        if signo < 0 {
+            continue;
+        }
+        if signo == libc::SIGCHLD {
+            if let Some(code) = reap(child) {
+                std::process::exit(code);
+            }
+            continue;
+        }
+        // Keys like Ctrl-C already signal the child's process group; only
+        // pass on signals some process sent.
+        if info.si_code <= 0
+            && let Ok(signal) = Signal::try_from(signo)
+        {
+            let _ = kill(child, signal);
+        }
+    }
+}
+
+/// Reap every exited process, returning the exit code once `child` is
+/// among them.
+fn reap(child: Pid) -> Option<i32> {
+    let mut code = None;
+    loop {
+        match waitpid(None, Some(WaitPidFlag::WNOHANG)) {
+            Ok(WaitStatus::Exited(pid, status)) if pid == child => code = Some(status),
+            Ok(WaitStatus::Signaled(pid, sig, _)) if pid == child => code = Some(128 + sig as i32),
+            Ok(WaitStatus::StillAlive) | Err(Errno::ECHILD) => return code,
+            Ok(_) | Err(Errno::EINTR) => continue,
+            Err(e) => {
+                eprintln!("waitpid error: {e}");
+                return code.or(Some(1));
+            }
+        }
+    }
+}
+
+/// Have the kernel kill this process when its parent dies, so a killed
+/// ajail does not leave the sandbox running.
+pub fn die_with_parent() {
+    unsafe { libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL, 0, 0, 0) };
+}
+
+/// Whether ajail's terminal hung up during the session.
+pub fn hung_up() -> bool {
+    HUNG_UP.load(Ordering::Relaxed)
+}
+
+/// In the parent: block the terminating signals in every thread and pass
+/// them on to `child` from a thread of their own. Must run before any other
+/// thread is spawned.
+///
+/// Without a pty of its own, the sandbox is in ajail's process group and
+/// already gets the signals the terminal sends, like Ctrl-C, so only
+/// signals some process sent are passed on.
+///
+/// The child is signalled through a pidfd, so nothing else is hit once it
+/// has been reaped. On SIGHUP the standard streams are pointed at
+/// /dev/null, so the post-session phase does not fail writing to a
+/// terminal that is gone.
+pub fn forward_signals(child: Pid, own_terminal: bool) -> io::Result<()> {
+    let pidfd = unsafe { libc::syscall(libc::SYS_pidfd_open, child.as_raw(), 0) };
+    if pidfd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let pidfd = unsafe { OwnedFd::from_raw_fd(pidfd as RawFd) };
+
+    let mut signals = SigSet::empty();
+    for signal in TERMINATING {
+        signals.add(signal);
+    }
+    signals.thread_block()?;
+
+    thread::spawn(move || {
+        loop {
+            let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+            let signo = unsafe { libc::sigwaitinfo(signals.as_ref(), &mut info) };
+            if signo < 0 {
+                continue;
+            }
+            if signo == libc::SIGHUP && !HUNG_UP.swap(true, Ordering::Relaxed) {
+                detach_from_terminal();
+            }
+            if own_terminal || info.si_code <= 0 {
+                unsafe {
+                    libc::syscall(
+                        libc::SYS_pidfd_send_signal,
+                        pidfd.as_raw_fd(),
+                        signo,
+                        std::ptr::null::<libc::siginfo_t>(),
+                        0,
+                    )
+                };
+            }
+        }
+    });
+    Ok(())
+}
+
+fn detach_from_terminal() {
+    if let Ok(null) = File::options().read(true).write(true).open("/dev/null") {
+        for fd in [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+            let _ = dup2(null.as_raw_fd(), fd);
+        }
+    }
+}
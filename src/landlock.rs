@@ -0,0 +1,201 @@
+use std::ffi::CString;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+// Filesystem access rights (linux/landlock.h)
+const ACCESS_FS_EXECUTE: u64 = 1 << 0;
+const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+const ACCESS_FS_READ_FILE: u64 = 1 << 2;
+const ACCESS_FS_READ_DIR: u64 = 1 << 3;
+const ACCESS_FS_REMOVE_DIR: u64 = 1 << 4;
+const ACCESS_FS_REMOVE_FILE: u64 = 1 << 5;
+const ACCESS_FS_MAKE_CHAR: u64 = 1 << 6;
+const ACCESS_FS_MAKE_DIR: u64 = 1 << 7;
+const ACCESS_FS_MAKE_REG: u64 = 1 << 8;
+const ACCESS_FS_MAKE_SOCK: u64 = 1 << 9;
+const ACCESS_FS_MAKE_FIFO: u64 = 1 << 10;
+const ACCESS_FS_MAKE_BLOCK: u64 = 1 << 11;
+const ACCESS_FS_MAKE_SYM: u64 = 1 << 12;
+const ACCESS_FS_REFER: u64 = 1 << 13; // ABI 2
+const ACCESS_FS_TRUNCATE: u64 = 1 << 14; // ABI 3
+
+const CREATE_RULESET_VERSION: u32 = 1;
+const RULE_PATH_BENEATH: u32 = 1;
+
+/// Rights that apply to regular files; rules on files may only use these.
+const ACCESS_FILE: u64 =
+    ACCESS_FS_EXECUTE | ACCESS_FS_WRITE_FILE | ACCESS_FS_READ_FILE | ACCESS_FS_TRUNCATE;
+
+/// Read and execute, granted on the whole visible filesystem.
+const ACCESS_READ: u64 = ACCESS_FS_EXECUTE | ACCESS_FS_READ_FILE | ACCESS_FS_READ_DIR;
+
+#[repr(C)]
+struct RulesetAttr {
+    handled_access_fs: u64,
+}
+
+#[repr(C, packed)]
+struct PathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: RawFd,
+}
+
+/// Filesystem rights handled (and therefore denied unless granted) for `abi`.
+fn handled_access(abi: i64) -> u64 {
+    let mut access = ACCESS_FS_EXECUTE
+        | ACCESS_FS_WRITE_FILE
+        | ACCESS_FS_READ_FILE
+        | ACCESS_FS_READ_DIR
+        | ACCESS_FS_REMOVE_DIR
+        | ACCESS_FS_REMOVE_FILE
+        | ACCESS_FS_MAKE_CHAR
+        | ACCESS_FS_MAKE_DIR
+        | ACCESS_FS_MAKE_REG
+        | ACCESS_FS_MAKE_SOCK
+        | ACCESS_FS_MAKE_FIFO
+        | ACCESS_FS_MAKE_BLOCK
+        | ACCESS_FS_MAKE_SYM;
+    if abi >= 2 {
+        access |= ACCESS_FS_REFER;
+    }
+    if abi >= 3 {
+        access |= ACCESS_FS_TRUNCATE;
+    }
+    access
+}
+
+/// Query the Landlock ABI version supported by the running kernel.
+fn abi_version() -> io::Result<i64> {
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            std::ptr::null::<RulesetAttr>(),
+            0usize,
+            CREATE_RULESET_VERSION,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(ret)
+}
+
+/// Grant `access` beneath `path`. Missing paths are skipped.
+fn add_path_rule(ruleset: &OwnedFd, path: &Path, access: u64) -> io::Result<()> {
+    let Ok(meta) = path.metadata() else {
+        return Ok(());
+    };
+    let access = if meta.is_dir() {
+        access
+    } else {
+        access & ACCESS_FILE
+    };
+
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let fd = unsafe { libc::open(c_path.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let parent = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    let attr = PathBeneathAttr {
+        allowed_access: access,
+        parent_fd: parent.as_raw_fd(),
+    };
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_add_rule,
+            ruleset.as_raw_fd(),
+            RULE_PATH_BENEATH,
+            &attr as *const PathBeneathAttr,
+            0u32,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Restrict the current process with a Landlock ruleset: read and execute
+/// everywhere, full access only beneath `writable`, and file writes under
+/// /dev and /proc (for /dev/null, /dev/tty and /proc/self).
+///
+/// Returns the ABI version in use, or `None` if the kernel lacks Landlock.
+pub fn restrict_filesystem(writable: &[&Path]) -> io::Result<Option<i64>> {
+    let Some((abi, ruleset)) = create_ruleset()? else {
+        return Ok(None);
+    };
+    let handled = handled_access(abi);
+
+    add_path_rule(&ruleset, Path::new("/"), ACCESS_READ)?;
+    for dir in ["/dev", "/proc"] {
+        add_path_rule(
+            &ruleset,
+            Path::new(dir),
+            ACCESS_READ | ACCESS_FS_WRITE_FILE | (handled & ACCESS_FS_TRUNCATE),
+        )?;
+    }
+    for path in writable {
+        add_path_rule(&ruleset, path, handled)?;
+    }
+
+    restrict_self(&ruleset)?;
+    Ok(Some(abi))
+}
+
+/// Deny the current process every filesystem access Landlock handles.
+///
+/// Returns the ABI version in use, or `None` if the kernel lacks Landlock.
+pub fn deny_filesystem() -> io::Result<Option<i64>> {
+    let Some((abi, ruleset)) = create_ruleset()? else {
+        return Ok(None);
+    };
+    restrict_self(&ruleset)?;
+    Ok(Some(abi))
+}
+
+/// Create a ruleset handling every right the kernel knows, or `None` if the
+/// kernel lacks Landlock.
+fn create_ruleset() -> io::Result<Option<(i64, OwnedFd)>> {
+    let abi = match abi_version() {
+        Ok(abi) => abi,
+        Err(e) if matches!(e.raw_os_error(), Some(libc::ENOSYS | libc::EOPNOTSUPP)) => {
+            return Ok(None);
+        }
+        Err(e) => return Err(e),
+    };
+
+    let attr = RulesetAttr {
+        handled_access_fs: handled_access(abi),
+    };
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            &attr as *const RulesetAttr,
+            std::mem::size_of::<RulesetAttr>(),
+            0u32,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Some((abi, unsafe { OwnedFd::from_raw_fd(fd as RawFd) })))
+}
+
+fn restrict_self(ruleset: &OwnedFd) -> io::Result<()> {
+    // Landlock requires no_new_privs for unprivileged processes.
+    let ret = unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let ret = unsafe { libc::syscall(libc::SYS_landlock_restrict_self, ruleset.as_raw_fd(), 0u32) };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
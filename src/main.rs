@@ -1,39 +1,116 @@
 mod config;
+mod dangerous;
+mod environ;
+mod glob;
+mod init;
+mod landlock;
+mod overlay;
 mod process;
+mod proxy;
+mod pty;
+mod record;
 mod sandbox;
 mod seccomp;
+mod seccomp_notify;
+mod seccomp_profile;
+mod session;
+mod syscalls;
 mod util;
 mod worktree;
 
 use std::env;
 use std::fs;
+use std::io;
+use std::os::unix::net::UnixStream;
 use std::path::PathBuf;
 use std::process::ExitCode;
 
-use clap::{Parser, ValueEnum};
+use clap::{Parser, Subcommand, ValueEnum};
 use nix::unistd::{ForkResult, fork};
+use serde::{Deserialize, Serialize};
 
 use config::{load_config, merge_options};
-use process::{handle_worktree_cleanup, run_child, wait_for_child};
+use overlay::{cleanup_overlay, create_overlay};
+use process::{handle_overlay_cleanup, handle_worktree_cleanup, run_child, wait_for_child};
+use proxy::EgressProxy;
+use record::Recording;
 use sandbox::SandboxConfig;
+use seccomp_notify::NotifyBroker;
+use session::SessionState;
 use util::{random_hex, repo_root};
-use worktree::create_worktree;
+use worktree::{cleanup_worktree, create_worktree};
 
 // =============================================================================
 // CLI
 // =============================================================================
 
-#[derive(Clone, Debug, Default, ValueEnum)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
 pub enum WorktreeAction {
-    /// Merge worktree changes into the original branch
+    /// Merge worktree changes into the original branch (overlay: apply to the project)
     Merge,
+    /// Rebase the session's commits onto the original branch, then merge
+    Rebase,
+    /// Squash the session's commits into one, then merge
+    Squash,
+    /// Keep the session branch, optionally renamed, and remove the worktree
+    Branch,
+    /// Write the session's commits as patches or a bundle, then remove them
+    Patch,
+    /// Keep the worktree and branch (overlay: the upper layer) as they are
+    Keep,
     /// Discard worktree changes
     Discard,
-    /// Interactively prompt for merge or discard
+    /// Interactively prompt for one of the above
     #[default]
     Prompt,
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NetworkMode {
+    /// Share the host network stack
+    #[default]
+    Host,
+    /// Fresh network namespace with only loopback
+    None,
+    /// Fresh network namespace; egress only via an allowlisting HTTP proxy
+    Proxy,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MergeUncommitted {
+    /// Commit them on the session branch before merging
+    #[default]
+    Commit,
+    /// Apply them to the original checkout as uncommitted changes after merging
+    Carry,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DirtyCheckout {
+    /// Refuse to merge and keep the worktree
+    #[default]
+    Abort,
+    /// Stash the uncommitted changes, merge, and re-apply them
+    Stash,
+    /// Merge into the branch, leaving the index and working tree as they are
+    NoCheckout,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CarryChanges {
+    /// Start the worktree from the committed HEAD
+    #[default]
+    None,
+    /// Also bring staged and unstaged changes to tracked files
+    Tracked,
+    /// Also bring untracked files, except ignored ones
+    All,
+}
+
 /// Run a command in a Linux namespace sandbox
 #[derive(Parser)]
 #[command(name = "ajail", version, about)]
@@ -54,6 +131,30 @@ pub struct Cli {
     #[arg(long)]
     pub allow_xdg_runtime: bool,
 
+    /// Network access: host, none, or proxy (default: host)
+    #[arg(long)]
+    pub network: Option<NetworkMode>,
+
+    /// Allow a host through the egress proxy (repeatable, `*.example.com` matches subdomains)
+    #[arg(long = "proxy-allow", value_name = "HOST")]
+    pub proxy_allow: Vec<String>,
+
+    /// Bind-mount a host path read-write: SRC[:DST] (repeatable)
+    #[arg(long, value_name = "SRC[:DST]")]
+    pub bind: Vec<String>,
+
+    /// Bind-mount a host path read-only: SRC[:DST] (repeatable)
+    #[arg(long, value_name = "SRC[:DST]")]
+    pub ro_bind: Vec<String>,
+
+    /// Mount an empty tmpfs at a path (repeatable)
+    #[arg(long, value_name = "PATH")]
+    pub tmpfs: Vec<PathBuf>,
+
+    /// Hide a path by masking it with an empty read-only mount (repeatable)
+    #[arg(long, value_name = "PATH")]
+    pub hide: Vec<PathBuf>,
+
     /// Override Claude config directory (default: ~/.claude, env: CLAUDE_CONFIG_DIR)
     #[arg(long, env = "CLAUDE_CONFIG_DIR")]
     pub claude_config_dir: Option<PathBuf>,
@@ -62,22 +163,94 @@ pub struct Cli {
     #[arg(long)]
     pub worktree: bool,
 
-    /// Action after worktree session ends: merge, discard, or prompt (default: prompt)
+    /// Uncommitted changes to bring into the worktree: none, tracked, or all (with untracked files)
+    #[arg(long, value_name = "MODE")]
+    pub carry_changes: Option<CarryChanges>,
+
+    /// Mount the project as a copy-on-write overlay; review changes after exit
+    #[arg(long, conflicts_with = "worktree")]
+    pub overlay: bool,
+
+    /// Action after worktree or overlay session ends (default: prompt)
     #[arg(long, default_value = "prompt")]
     pub worktree_action: WorktreeAction,
 
+    /// Name for the branch kept by `--worktree-action branch` (default: ajail-<session_id>)
+    #[arg(long, value_name = "NAME")]
+    pub branch_name: Option<String>,
+
+    /// Where `--worktree-action patch` writes: a directory of patches, or a file ending in .bundle (default: ajail-<session_id>-patches)
+    #[arg(long, value_name = "PATH")]
+    pub patch_output: Option<PathBuf>,
+
+    /// Days a discarded worktree session is kept for `ajail sessions restore`, 0 to keep none (default: 30)
+    #[arg(long, value_name = "DAYS")]
+    pub discard_retention_days: Option<u64>,
+
+    /// What merging does with uncommitted changes in the worktree: commit or carry (default: commit)
+    #[arg(long, value_name = "MODE")]
+    pub merge_uncommitted: Option<MergeUncommitted>,
+
+    /// What merging does when the original checkout has uncommitted changes: abort, stash, or no-checkout (default: abort)
+    #[arg(long, value_name = "MODE")]
+    pub dirty_checkout: Option<DirtyCheckout>,
+
     /// Allow writing to dangerous files (.bashrc, .gitconfig, .git/hooks, etc.)
     #[arg(long)]
     pub allow_dangerous_writes: bool,
 
+    /// Block creation of missing dangerous paths with read-only placeholders
+    #[arg(long)]
+    pub block_dangerous_creation: bool,
+
+    /// Restrict filesystem writes with Landlock as a second line of defense
+    #[arg(long)]
+    pub landlock: bool,
+
     /// Allow creating Unix domain sockets (blocked by default via seccomp)
     #[arg(long)]
     pub allow_unix_sockets: bool,
 
+    /// Allow 32-bit (i386, arm) syscalls, which are killed by default
+    #[arg(long)]
+    pub allow_compat_syscalls: bool,
+
+    /// Allow io_uring (denied by default; weakens the Unix socket block)
+    #[arg(long)]
+    pub allow_io_uring: bool,
+
+    /// Do not install the default seccomp hardening profile (bpf, keyctl, ptrace attach, etc.)
+    #[arg(long)]
+    pub disable_seccomp_hardening: bool,
+
+    /// Also enforce an OCI/Docker seccomp profile (JSON)
+    #[arg(long, value_name = "PATH")]
+    pub seccomp_profile: Option<PathBuf>,
+
+    /// Ask before letting the sandbox connect to Unix sockets, instead of blocking them
+    #[arg(long)]
+    pub seccomp_notify: bool,
+
+    /// Let the sandbox connect to Unix sockets matching a path pattern without asking (repeatable)
+    #[arg(long = "unix-socket-allow", value_name = "PATH")]
+    pub unix_socket_allow: Vec<String>,
+
+    /// Forward only a minimal base environment plus variables named with --env
+    #[arg(long)]
+    pub clean_env: bool,
+
+    /// Forward a host variable (KEY) or set one (KEY=VALUE) inside the sandbox (repeatable)
+    #[arg(long, value_name = "KEY[=VALUE]")]
+    pub env: Vec<String>,
+
     /// Command to run inside the sandbox (default: claude)
     #[arg(long)]
     pub command: Option<String>,
 
+    /// Record the terminal session to an asciicast v2 file
+    #[arg(long, value_name = "FILE")]
+    pub record: Option<PathBuf>,
+
     /// Pass --dangerously-skip-permissions to the command
     #[arg(long)]
     pub dangerously_skip_permissions: bool,
@@ -85,6 +258,34 @@ pub struct Cli {
     /// Extra arguments to pass to the command (after --)
     #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
     pub extra_args: Vec<String>,
+
+    #[command(subcommand)]
+    pub subcommand: Option<Commands>,
+}
+
+#[derive(Subcommand)]
+pub enum Commands {
+    /// Manage sessions left in this repo: worktrees, overlays and ajail-* branches
+    Sessions {
+        #[command(subcommand)]
+        action: SessionsAction,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SessionsAction {
+    /// List the sessions in this repo
+    List,
+    /// Show a session's commits and changed files
+    Show { id: String },
+    /// Merge a session's changes and remove it
+    Merge { id: String },
+    /// Remove a session and its changes
+    Discard { id: String },
+    /// Remove sessions without changes and what is left of finished ones
+    Clean,
+    /// Bring a discarded session back as its ajail-<id> branch
+    Restore { id: String },
 }
 
 // =============================================================================
@@ -95,6 +296,9 @@ fn main() -> ExitCode {
     let cli = Cli::parse();
     let config = load_config();
     let options = merge_options(&cli, &config);
+    if let Some(Commands::Sessions { ref action }) = cli.subcommand {
+        return session::run_command(action, &options);
+    }
 
     let project_dir = env::current_dir().expect("Failed to get current directory");
     let repo_root = repo_root(&project_dir);
@@ -137,9 +341,51 @@ fn main() -> ExitCode {
         home.join(".claude")
     };
 
+    if options.worktree && options.overlay {
+        eprintln!("Worktree and overlay modes cannot be used together");
+        return ExitCode::FAILURE;
+    }
+
+    // Sessions whose ajail was killed before cleaning up
+    session::recover_orphans(&real_repo_root, &options);
+    worktree::expire_discarded(&real_repo_root, options.discard_retention_days);
+
+    // Reject bad seccomp rules before creating a worktree or overlay
+    if let Err(e) = seccomp::sandbox_policy(&options).compile() {
+        eprintln!("Invalid seccomp rules: {e}");
+        return ExitCode::FAILURE;
+    }
+    let seccomp_profile = match &options.seccomp_profile {
+        Some(path) => match seccomp_profile::load(path) {
+            Ok(policy) => Some(policy),
+            Err(e) => {
+                eprintln!("Invalid seccomp profile {}: {e}", path.display());
+                return ExitCode::FAILURE;
+            }
+        },
+        None => None,
+    };
+
+    // Create the recording before any setup, so a bad path fails early
+    let record_path = options.record.clone().or_else(|| {
+        options
+            .record_sessions
+            .then(|| record::session_recording_path(&session_id))
+    });
+    let recording = match &record_path {
+        Some(path) => match Recording::create(path) {
+            Ok(recording) => Some(recording),
+            Err(e) => {
+                eprintln!("Failed to create recording {}: {e}", path.display());
+                return ExitCode::FAILURE;
+            }
+        },
+        None => None,
+    };
+
     // Worktree: create an isolated worktree to work in
     let worktree_info = if options.worktree {
-        match create_worktree(&real_repo_root, &session_id) {
+        match create_worktree(&real_repo_root, &session_id, options.carry_changes) {
             Ok(info) => {
                 eprintln!(
                     "Created worktree at {} (branch: {})",
@@ -157,6 +403,35 @@ fn main() -> ExitCode {
         None
     };
 
+    // Overlay: writes to the project land in a per-session upper layer
+    let overlay_info = if options.overlay {
+        match create_overlay(&real_repo_root, &session_id) {
+            Ok(info) => {
+                eprintln!(
+                    "Created overlay for {} (upper layer: {})",
+                    info.repo_root.display(),
+                    info.upper_dir.display()
+                );
+                Some(info)
+            }
+            Err(e) => {
+                eprintln!("Failed to create overlay: {e}");
+                return ExitCode::FAILURE;
+            }
+        }
+    } else {
+        None
+    };
+
+    // Saved until the post-session phase is done, so a later run can clean
+    // up if this one is killed
+    let mut session = SessionState::new(&session_id, &real_repo_root, &tmp_home);
+    session.worktree = worktree_info.clone();
+    session.overlay = overlay_info.clone();
+    if let Err(e) = session.save() {
+        eprintln!("ajail: warning: failed to save session state: {e}");
+    }
+
     // If using a worktree, sandbox operates on the worktree path instead
     let (sandbox_repo_root, sandbox_project_dir, sandbox_share_tree) =
         if let Some(ref wt) = worktree_info {
@@ -182,7 +457,7 @@ fn main() -> ExitCode {
             std::process::exit(1);
         });
 
-    let sandbox_config = SandboxConfig {
+    let mut sandbox_config = SandboxConfig {
         home: home.clone(),
         claude_config,
         claude_config_dest: claude_config_dest.clone(),
@@ -191,24 +466,207 @@ fn main() -> ExitCode {
         repo_root: sandbox_repo_root,
         project_dir: sandbox_project_dir,
         original_git_dir,
+        overlay: overlay_info.clone(),
+        placeholders: Vec::new(),
         options,
     };
 
+    // In proxy mode the child hands a listener bound inside its network
+    // namespace back to us over a channel; with the notify broker, the
+    // seccomp listener.
+    let channels = (|| {
+        let proxy = (sandbox_config.options.network == NetworkMode::Proxy)
+            .then(UnixStream::pair)
+            .transpose()?;
+        let notify = seccomp::brokers_unix_sockets(&sandbox_config.options)
+            .then(UnixStream::pair)
+            .transpose()?;
+        io::Result::Ok((proxy, notify))
+    })();
+    let (proxy_channel, notify_channel) = match channels {
+        Ok(channels) => channels,
+        Err(e) => {
+            eprintln!("Failed to create sandbox channel: {e}");
+            if let Some(ref info) = worktree_info {
+                cleanup_worktree(info);
+            }
+            if let Some(ref info) = overlay_info {
+                cleanup_overlay(info);
+            }
+            let _ = fs::remove_dir_all(&tmp_home);
+            session.finish(false);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // Snapshot dangerous paths so newly created ones can be reported afterwards.
+    // Placeholders are created first so they are protected like existing paths.
+    // In overlay mode new paths can only appear in the upper layer.
+    let protect_dangerous = !sandbox_config.options.allow_dangerous_writes;
+    let placeholders =
+        (protect_dangerous && sandbox_config.options.block_dangerous_creation).then(|| {
+            dangerous::create_placeholders(
+                &sandbox_config.repo_root,
+                sandbox_config.original_git_dir.as_deref(),
+                &sandbox_config.options,
+            )
+        });
+    if let Some(ref placeholders) = placeholders {
+        sandbox_config.placeholders = placeholders.masked().to_vec();
+        session.placeholders = Some(placeholders.clone());
+        let _ = session.save();
+    }
+    let dangerous_root = overlay_info
+        .as_ref()
+        .map_or(&sandbox_config.repo_root, |o| &o.upper_dir);
+    let dangerous_git_dir = sandbox_config.original_git_dir.as_deref();
+    let dangerous_before = protect_dangerous.then(|| {
+        dangerous::find_dangerous_paths(dangerous_root, dangerous_git_dir, &sandbox_config.options)
+    });
+
+    // On a terminal, the sandbox gets a pty of its own and the parent relays
+    // to it, so the sandboxed command never touches the host terminal.
+    let pty = pty::open().unwrap_or_else(|e| {
+        eprintln!("ajail: warning: failed to open a pty, sharing the terminal: {e}");
+        None
+    });
+    let recording = match (&pty, recording) {
+        (Some(pty), Some(mut recording)) => {
+            let command: Vec<String> = std::iter::once(sandbox_config.options.command.clone())
+                .chain(cli.extra_args.iter().cloned())
+                .collect();
+            match recording.start(pty.size(), &session_id, &command, &sandbox_config.options) {
+                Ok(()) => Some(recording),
+                Err(e) => {
+                    eprintln!("ajail: warning: failed to start recording: {e}");
+                    recording.discard();
+                    None
+                }
+            }
+        }
+        (None, Some(recording)) => {
+            eprintln!("ajail: warning: not running on a terminal, session not recorded");
+            recording.discard();
+            None
+        }
+        (_, None) => None,
+    };
+    let recording_path = recording.as_ref().map(|r| r.path().to_path_buf());
+
     // Fork: child sets up namespace and execs command, parent waits
     match unsafe { fork() } {
         Ok(ForkResult::Child) => {
-            run_child(&sandbox_config, &cli, &claude_config_dest, &command_path);
+            init::die_with_parent();
+            if let Some(pty) = pty
+                && let Err(e) = pty.attach()
+            {
+                eprintln!("Failed to attach to pty: {e}");
+                std::process::exit(1);
+            }
+            let proxy_channel = proxy_channel.map(|(_, child_end)| child_end);
+            let notify_channel = notify_channel.map(|(_, child_end)| child_end);
+            run_child(
+                &sandbox_config,
+                &cli,
+                &claude_config_dest,
+                &command_path,
+                proxy_channel.as_ref(),
+                seccomp_profile.as_ref(),
+                notify_channel.as_ref(),
+            );
         }
         Ok(ForkResult::Parent { child }) => {
+            // Signals for ajail go to the sandbox instead, so the
+            // post-session phase below always runs
+            if let Err(e) = init::forward_signals(child, pty.is_some()) {
+                eprintln!("ajail: warning: failed to forward signals to the sandbox: {e}");
+            }
+            let pty_proxy = pty.and_then(|pty| match pty.spawn_proxy(recording) {
+                Ok(proxy) => Some(proxy),
+                Err(e) => {
+                    eprintln!("Failed to start pty proxy: {e}");
+                    None
+                }
+            });
+            let proxy = proxy_channel.and_then(|(parent_end, child_end)| {
+                drop(child_end);
+                match proxy::receive_listener(&parent_end) {
+                    Ok(listener) => Some(EgressProxy::spawn(
+                        listener,
+                        sandbox_config.options.proxy_allow.clone(),
+                    )),
+                    Err(e) => {
+                        eprintln!("Failed to start egress proxy: {e}");
+                        None
+                    }
+                }
+            });
+            let broker = notify_channel.and_then(|(parent_end, child_end)| {
+                drop(child_end);
+                match seccomp_notify::receive_listener(&parent_end) {
+                    Ok(listener) => Some(NotifyBroker::spawn(
+                        listener,
+                        sandbox_config.options.unix_socket_allow.clone(),
+                    )),
+                    Err(e) => {
+                        eprintln!("Failed to start seccomp notify broker: {e}");
+                        None
+                    }
+                }
+            });
             let exit_code = wait_for_child(child);
-            handle_worktree_cleanup(&worktree_info, &cli.worktree_action);
+            if let Some(pty_proxy) = pty_proxy {
+                pty_proxy.finish();
+            }
+            if let Some(ref path) = recording_path {
+                eprintln!("ajail: session recorded to {}", path.display());
+            }
+            if let Some(ref proxy) = proxy {
+                proxy.report_denied();
+            }
+            if let Some(ref broker) = broker {
+                broker.report();
+            }
+            if let Some(ref before) = dangerous_before {
+                dangerous::report_new_dangerous_paths(
+                    dangerous_root,
+                    dangerous_git_dir,
+                    &sandbox_config.options,
+                    before,
+                );
+            }
+            if let Some(ref placeholders) = placeholders {
+                placeholders.remove();
+            }
+            // With the terminal gone nobody can answer the prompt; keep
+            // the changes for the next run to offer
+            let keep = init::hung_up() && matches!(cli.worktree_action, WorktreeAction::Prompt);
+            let mut kept = false;
+            if !keep {
+                kept |= handle_worktree_cleanup(
+                    &worktree_info,
+                    &cli.worktree_action,
+                    &sandbox_config.options,
+                );
+                kept |= handle_overlay_cleanup(&overlay_info, &cli.worktree_action);
+            }
             let _ = fs::remove_dir_all(&tmp_home);
+            session.finish(kept);
             ExitCode::from(exit_code as u8)
         }
         Err(e) => {
             eprintln!("Fork failed: {e}");
-            handle_worktree_cleanup(&worktree_info, &cli.worktree_action);
+            if let Some(ref placeholders) = placeholders {
+                placeholders.remove();
+            }
+            let kept_worktree = handle_worktree_cleanup(
+                &worktree_info,
+                &cli.worktree_action,
+                &sandbox_config.options,
+            );
+            let kept_overlay = handle_overlay_cleanup(&overlay_info, &cli.worktree_action);
             let _ = fs::remove_dir_all(&tmp_home);
+            session.finish(kept_worktree || kept_overlay);
             ExitCode::FAILURE
         }
     }
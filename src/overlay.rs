@@ -0,0 +1,315 @@
+use std::env;
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt, symlink};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::worktree::prompt_text;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct OverlayInfo {
+    /// Per-session directory holding the upper and work layers
+    pub session_dir: PathBuf,
+    pub upper_dir: PathBuf,
+    pub work_dir: PathBuf,
+    /// The real project tree, used as the read-only lower layer
+    pub repo_root: PathBuf,
+    /// When the session was set up, as `(seconds, nanoseconds)` from the
+    /// filesystem's clock. Lower entries with a later ctime were changed on
+    /// the host during the session.
+    #[serde(default)]
+    pub started: Option<(i64, i64)>,
+}
+
+/// A single change recorded in the upper layer.
+enum Change {
+    Added(PathBuf),
+    Modified(PathBuf),
+    Deleted(PathBuf),
+}
+
+pub fn create_overlay(repo_root: &Path, session_id: &str) -> Result<OverlayInfo, String> {
+    // overlayfs mount options use ',' and ':' as separators
+    for path in [repo_root, &env::temp_dir()] {
+        if path.to_string_lossy().contains([',', ':']) {
+            return Err(format!(
+                "Path contains ',' or ':', which overlayfs cannot handle: {}",
+                path.display()
+            ));
+        }
+    }
+
+    let session_dir = env::temp_dir().join(format!("ajail-overlay-{session_id}"));
+    let upper_dir = session_dir.join("upper");
+    let work_dir = session_dir.join("work");
+    for dir in [&upper_dir, &work_dir] {
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {e}", dir.display()))?;
+    }
+    // Timestamps come from a coarse clock, so take the start from a fresh
+    // entry rather than from the system time.
+    let started = fs::metadata(&work_dir)
+        .map(|meta| (meta.ctime(), meta.ctime_nsec()))
+        .map_err(|e| format!("Failed to stat {}: {e}", work_dir.display()))?;
+
+    Ok(OverlayInfo {
+        session_dir,
+        upper_dir,
+        work_dir,
+        repo_root: repo_root.to_path_buf(),
+        started: Some(started),
+    })
+}
+
+pub fn overlay_has_changes(info: &OverlayInfo) -> bool {
+    fs::read_dir(&info.upper_dir)
+        .map(|mut entries| entries.next().is_some())
+        .unwrap_or(false)
+}
+
+/// Whether `meta` describes an overlayfs whiteout (a 0:0 character device).
+pub fn is_whiteout(meta: &fs::Metadata) -> bool {
+    meta.file_type().is_char_device() && meta.rdev() == 0
+}
+
+/// Whether `dir` is an opaque directory, i.e. it replaces the lower directory
+/// entirely instead of merging with it.
+fn is_opaque(dir: &Path) -> bool {
+    overlay_xattr(dir, "opaque").is_some_and(|value| value == b"y")
+}
+
+/// Whether `path` was copied up from the lower layer, as opposed to created
+/// in the session. Only known where the lower filesystem supports file
+/// handles; elsewhere overlayfs records no origin.
+fn is_copied_up(path: &Path) -> bool {
+    overlay_xattr(path, "origin").is_some()
+}
+
+/// The overlayfs xattr `name` of `path`, up to 64 bytes of it.
+fn overlay_xattr(path: &Path, name: &str) -> Option<Vec<u8>> {
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    ["user.overlay.", "trusted.overlay."]
+        .iter()
+        .find_map(|prefix| {
+            let name = CString::new(format!("{prefix}{name}")).expect("no NUL in name");
+            let mut value = [0u8; 64];
+            let len = unsafe {
+                libc::lgetxattr(
+                    path.as_ptr(),
+                    name.as_ptr(),
+                    value.as_mut_ptr().cast(),
+                    value.len(),
+                )
+            };
+            // Longer values fail with ERANGE, but still exist.
+            if len >= 0 {
+                Some(value[..len as usize].to_vec())
+            } else {
+                (io::Error::last_os_error().raw_os_error() == Some(libc::ERANGE)).then(Vec::new)
+            }
+        })
+}
+
+fn collect_changes(upper: &Path, lower: &Path, rel: &Path, out: &mut Vec<Change>) {
+    let Ok(entries) = fs::read_dir(upper) else {
+        return;
+    };
+    let mut entries: Vec<_> = entries.flatten().collect();
+    entries.sort_by_key(|e| e.file_name());
+
+    for entry in entries {
+        let rel = rel.join(entry.file_name());
+        let lower_path = lower.join(entry.file_name());
+        let Ok(meta) = entry.path().symlink_metadata() else {
+            continue;
+        };
+        let in_lower = lower_path.symlink_metadata().is_ok();
+
+        if is_whiteout(&meta) {
+            out.push(Change::Deleted(rel));
+        } else if meta.is_dir() {
+            if in_lower && is_opaque(&entry.path()) {
+                out.push(Change::Deleted(rel.clone()));
+            }
+            collect_changes(&entry.path(), &lower_path, &rel, out);
+        } else if in_lower {
+            out.push(Change::Modified(rel));
+        } else {
+            out.push(Change::Added(rel));
+        }
+    }
+}
+
+pub fn show_overlay_changes(info: &OverlayInfo) {
+    let mut changes = Vec::new();
+    collect_changes(
+        &info.upper_dir,
+        &info.repo_root,
+        Path::new(""),
+        &mut changes,
+    );
+
+    let mut git_changes = 0;
+    for change in &changes {
+        let (tag, path) = match change {
+            Change::Added(p) => ('A', p),
+            Change::Modified(p) => ('M', p),
+            Change::Deleted(p) => ('D', p),
+        };
+        if path.starts_with(".git") {
+            git_changes += 1;
+            continue;
+        }
+        eprintln!("{tag} {}", path.display());
+    }
+    if git_changes > 0 {
+        eprintln!("({git_changes} changes to git metadata: commits, index or refs)");
+    }
+    eprintln!("Upper layer: {}", info.upper_dir.display());
+}
+
+/// Remove `path` whether it is a file, symlink or directory.
+fn remove_path(path: &Path) -> io::Result<()> {
+    match path.symlink_metadata() {
+        Ok(meta) if meta.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Lower paths that applying the upper layer would overwrite or delete but
+/// that changed on the host since `started`: their ctime is later, or they
+/// are gone although the session modified them. ctime cannot be set from
+/// user space, so a restored mtime does not hide a change.
+fn host_changes(
+    upper: &Path,
+    lower: &Path,
+    rel: &Path,
+    started: (i64, i64),
+    out: &mut Vec<PathBuf>,
+) {
+    let Ok(entries) = fs::read_dir(upper) else {
+        return;
+    };
+    let mut entries: Vec<_> = entries.flatten().collect();
+    entries.sort_by_key(|e| e.file_name());
+
+    for entry in entries {
+        let src = entry.path();
+        let rel = rel.join(entry.file_name());
+        let Ok(meta) = src.symlink_metadata() else {
+            continue;
+        };
+        let lower_path = lower.join(entry.file_name());
+        let lower_meta = lower_path.symlink_metadata().ok();
+        let changed = match lower_meta {
+            Some(ref lower_meta) => {
+                // A merged directory's own ctime moves with every entry the
+                // host adds; only its entries count, checked below.
+                let merged = meta.is_dir() && lower_meta.is_dir() && !is_opaque(&src);
+                !merged && (lower_meta.ctime(), lower_meta.ctime_nsec()) >= started
+            }
+            None => !is_whiteout(&meta) && is_copied_up(&src),
+        };
+        if changed {
+            out.push(rel.clone());
+        }
+        if meta.is_dir() && !is_opaque(&src) {
+            host_changes(&src, &lower_path, &rel, started, out);
+        }
+    }
+}
+
+/// Replay the upper layer onto the lower directory.
+fn apply_dir(upper: &Path, lower: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(upper)? {
+        let entry = entry?;
+        let src = entry.path();
+        let dst = lower.join(entry.file_name());
+        let meta = src.symlink_metadata()?;
+        let file_type = meta.file_type();
+
+        if is_whiteout(&meta) {
+            remove_path(&dst)?;
+        } else if file_type.is_dir() {
+            let dst_is_dir = dst.symlink_metadata().is_ok_and(|m| m.is_dir());
+            if !dst_is_dir || is_opaque(&src) {
+                remove_path(&dst)?;
+            }
+            fs::create_dir_all(&dst)?;
+            apply_dir(&src, &dst)?;
+            fs::set_permissions(&dst, fs::Permissions::from_mode(meta.mode()))?;
+        } else if file_type.is_symlink() {
+            remove_path(&dst)?;
+            symlink(fs::read_link(&src)?, &dst)?;
+        } else {
+            if dst.symlink_metadata().is_ok_and(|m| !m.is_file()) {
+                remove_path(&dst)?;
+            }
+            fs::copy(&src, &dst)?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether to apply the overlay although the host changed some of the paths
+/// it touches. Asks on /dev/tty; without a terminal, the answer is no.
+fn confirm_host_changes(info: &OverlayInfo) -> bool {
+    let Some(started) = info.started else {
+        return true;
+    };
+    let mut changed = Vec::new();
+    host_changes(
+        &info.upper_dir,
+        &info.repo_root,
+        Path::new(""),
+        started,
+        &mut changed,
+    );
+    if changed.is_empty() {
+        return true;
+    }
+
+    eprintln!("These paths changed on the host during the session, applying would overwrite them:");
+    for path in &changed {
+        eprintln!("  {}", path.display());
+    }
+    prompt_text("Apply the overlay anyway? (y/n)", "n")
+        .is_some_and(|answer| matches!(answer.as_str(), "y" | "yes"))
+}
+
+pub fn apply_overlay(info: &OverlayInfo) -> bool {
+    if !confirm_host_changes(info) {
+        eprintln!(
+            "Not applying overlay changes. Upper layer preserved at: {}",
+            info.upper_dir.display()
+        );
+        return false;
+    }
+    match apply_dir(&info.upper_dir, &info.repo_root) {
+        Ok(()) => {
+            eprintln!("Applied overlay changes to {}.", info.repo_root.display());
+            true
+        }
+        Err(e) => {
+            eprintln!(
+                "Failed to apply overlay changes: {e}. Upper layer preserved at: {}",
+                info.upper_dir.display()
+            );
+            false
+        }
+    }
+}
+
+pub fn cleanup_overlay(info: &OverlayInfo) {
+    // overlayfs leaves its work/work directory with mode 000
+    let _ = fs::set_permissions(
+        info.work_dir.join("work"),
+        fs::Permissions::from_mode(0o700),
+    );
+    let _ = fs::remove_dir_all(&info.session_dir);
+}
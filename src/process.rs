@@ -1,37 +1,93 @@
 use std::env;
 use std::ffi::CString;
 use std::os::unix::ffi::OsStrExt;
-use std::path::Path;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
 
+use clap::ValueEnum;
 use nix::sys::wait::{WaitStatus, waitpid};
 use nix::unistd::{Pid, chdir, execve};
 
+use crate::config::Options;
+use crate::environ;
+use crate::landlock;
+use crate::overlay::{
+    OverlayInfo, apply_overlay, cleanup_overlay, overlay_has_changes, show_overlay_changes,
+};
+use crate::proxy;
 use crate::sandbox::{self, SandboxConfig};
-use crate::seccomp;
+use crate::seccomp::{self, Policy};
+use crate::seccomp_notify;
 use crate::worktree::{
-    WorktreeInfo, cleanup_worktree, merge_worktree, prompt_worktree_action, show_worktree_diff,
-    worktree_has_changes,
+    WorktreeInfo, archive_worktree, carry_uncommitted, cleanup_worktree, commit_uncommitted,
+    keep_branch, merge_worktree, prompt_text, prompt_worktree_action, rebase_worktree,
+    show_worktree_diff, squash_worktree, worktree_has_changes, write_patches,
 };
-use crate::{Cli, WorktreeAction};
+use crate::{Cli, MergeUncommitted, WorktreeAction};
 
 /// Set up the sandbox namespace and exec the command. Never returns on success.
+///
+/// `proxy_channel` is set in proxy network mode: the child binds the proxy
+/// listener inside its network namespace and sends it to the parent.
+/// `seccomp_profile` is an imported profile, stacked on the built-in filter.
+/// `notify_channel` is set when Unix socket connections are brokered: the
+/// child sends the seccomp listener to the parent over it.
 pub fn run_child(
     sandbox_config: &SandboxConfig,
     cli: &Cli,
     claude_config_dest: &Path,
     command_path: &Path,
+    proxy_channel: Option<&UnixStream>,
+    seccomp_profile: Option<&Policy>,
+    notify_channel: Option<&UnixStream>,
 ) -> ! {
+    let stripped = environ::apply_env_policy(&sandbox_config.options);
+    environ::report_stripped(&stripped);
+
     if let Err(e) = sandbox::setup_namespace(sandbox_config) {
         eprintln!("Failed to set up sandbox: {e}");
         std::process::exit(1);
     }
 
-    if !sandbox_config.options.allow_unix_sockets
-        && let Err(e) = seccomp::block_unix_sockets()
+    if let Some(channel) = proxy_channel {
+        let port = match proxy::send_listener(channel) {
+            Ok(port) => port,
+            Err(e) => {
+                eprintln!("Failed to set up egress proxy: {e}");
+                std::process::exit(1);
+            }
+        };
+        // Safety: we're in a forked child process, single-threaded.
+        let url = format!("http://127.0.0.1:{port}");
+        for var in ["HTTPS_PROXY", "https_proxy", "HTTP_PROXY", "http_proxy"] {
+            unsafe { env::set_var(var, &url) };
+        }
+        unsafe { env::set_var("NO_PROXY", "localhost,127.0.0.1,::1") };
+        unsafe { env::set_var("no_proxy", "localhost,127.0.0.1,::1") };
+    }
+
+    let policy = seccomp::sandbox_policy(&sandbox_config.options);
+    if let Some(channel) = notify_channel {
+        if let Err(e) = policy
+            .install_with_listener()
+            .and_then(|listener| seccomp_notify::send_listener(channel, listener))
+        {
+            eprintln!("Failed to set up seccomp notify broker: {e}");
+            std::process::exit(1);
+        }
+    } else if !policy.rules.is_empty()
+        && let Err(e) = policy.install()
     {
         eprintln!("Failed to install seccomp filter: {e}");
         std::process::exit(1);
     }
+    // The kernel runs every installed filter and applies the strictest result.
+    if let Some(profile) = seccomp_profile
+        && let Err(e) = profile.install()
+    {
+        eprintln!("Failed to install seccomp profile: {e}");
+        std::process::exit(1);
+    }
 
     if let Err(e) = chdir(&sandbox_config.project_dir) {
         eprintln!("Failed to chdir to project: {e}");
@@ -68,11 +124,53 @@ pub fn run_child(
     for arg in &cli.extra_args {
         args.push(CString::new(arg.as_bytes()).expect("arg contains NUL byte"));
     }
+    if sandbox_config.options.landlock {
+        let writable = landlock_writable_paths(sandbox_config, claude_config_dest);
+        let writable: Vec<&Path> = writable.iter().map(PathBuf::as_path).collect();
+        match landlock::restrict_filesystem(&writable) {
+            Ok(Some(abi)) => eprintln!("ajail: Landlock ABI v{abi} enforced"),
+            Ok(None) => {
+                eprintln!("ajail: warning: kernel does not support Landlock, continuing without it")
+            }
+            Err(e) => {
+                eprintln!("Failed to apply Landlock ruleset: {e}");
+                std::process::exit(1);
+            }
+        }
+    }
+
     let Err(e) = execve(&cmd, &args, &env_vars);
     eprintln!("Failed to exec {cmd_name}: {e}");
     std::process::exit(1);
 }
 
+/// Paths the sandboxed command may write to under Landlock: the project,
+/// the sandbox's tmpfs home (which holds the Claude config), /tmp, and
+/// user-added read-write mounts.
+fn landlock_writable_paths(
+    sandbox_config: &SandboxConfig,
+    claude_config_dest: &Path,
+) -> Vec<PathBuf> {
+    let options = &sandbox_config.options;
+    [
+        sandbox_config.repo_root.clone(),
+        sandbox_config.home.clone(),
+        claude_config_dest.to_path_buf(),
+        PathBuf::from("/tmp"),
+    ]
+    .into_iter()
+    .chain(sandbox_config.original_git_dir.clone())
+    .chain(
+        options
+            .bind_mounts
+            .iter()
+            .filter(|m| !m.readonly)
+            .map(|m| m.dst.clone()),
+    )
+    .chain(options.tmpfs_mounts.iter().cloned())
+    .collect()
+}
+
 /// Wait for the child process to exit and return its exit code.
 pub fn wait_for_child(child: Pid) -> i32 {
     loop {
@@ -89,39 +187,174 @@ pub fn wait_for_child(child: Pid) -> i32 {
     }
 }
 
+/// Actions offered for a worktree, in prompt order.
+const WORKTREE_CHOICES: [WorktreeAction; 7] = [
+    WorktreeAction::Merge,
+    WorktreeAction::Rebase,
+    WorktreeAction::Squash,
+    WorktreeAction::Branch,
+    WorktreeAction::Patch,
+    WorktreeAction::Keep,
+    WorktreeAction::Discard,
+];
+
+/// Actions offered for an overlay, which has no commits to work with.
+const OVERLAY_CHOICES: [WorktreeAction; 3] = [
+    WorktreeAction::Merge,
+    WorktreeAction::Keep,
+    WorktreeAction::Discard,
+];
+
 /// Handle post-session worktree cleanup (merge, discard, or prompt).
+/// Returns whether the worktree was kept on purpose.
 pub fn handle_worktree_cleanup(
     worktree_info: &Option<WorktreeInfo>,
     worktree_action: &WorktreeAction,
-) {
+    options: &Options,
+) -> bool {
     let Some(info) = worktree_info else {
-        return;
+        return false;
     };
 
     if !worktree_has_changes(info) {
         eprintln!("No changes made in worktree.");
         cleanup_worktree(info);
-        return;
+        return false;
     }
 
     eprintln!("\n--- Worktree changes ---");
     show_worktree_diff(info);
 
+    let prompted = *worktree_action == WorktreeAction::Prompt;
     let action = match worktree_action {
-        WorktreeAction::Prompt => prompt_worktree_action(),
-        a => a.clone(),
+        WorktreeAction::Prompt => prompt_worktree_action(&WORKTREE_CHOICES),
+        a => *a,
     };
+    let session_id = info.branch_name.trim_start_matches("ajail-");
 
     match action {
-        WorktreeAction::Merge => {
-            if merge_worktree(info) {
+        WorktreeAction::Merge | WorktreeAction::Rebase | WorktreeAction::Squash => {
+            let dirty = options.dirty_checkout;
+            // Rebasing and squashing rewrite the worktree, so uncommitted
+            // changes are always committed first
+            let merged = match (action, options.merge_uncommitted) {
+                (WorktreeAction::Rebase, _) => {
+                    commit_uncommitted(info) && rebase_worktree(info) && merge_worktree(info, dirty)
+                }
+                (WorktreeAction::Squash, _) => {
+                    commit_uncommitted(info) && squash_worktree(info) && merge_worktree(info, dirty)
+                }
+                (_, MergeUncommitted::Commit) => {
+                    commit_uncommitted(info) && merge_worktree(info, dirty)
+                }
+                (_, MergeUncommitted::Carry) => {
+                    merge_worktree(info, dirty) && carry_uncommitted(info)
+                }
+            };
+            if merged {
                 cleanup_worktree(info);
             }
             // If merge failed, don't clean up — user can resolve
         }
+        WorktreeAction::Branch => {
+            let name = options
+                .branch_name
+                .clone()
+                .or_else(|| {
+                    prompted
+                        .then(|| prompt_text("Branch name", &info.branch_name))
+                        .flatten()
+                })
+                .unwrap_or_else(|| info.branch_name.clone());
+            if commit_uncommitted(info) {
+                keep_branch(info, &name);
+            }
+        }
+        WorktreeAction::Patch => {
+            let default = format!("ajail-{session_id}-patches");
+            let path = options
+                .patch_output
+                .clone()
+                .or_else(|| {
+                    prompted
+                        .then(|| {
+                            prompt_text(
+                                "Write to (a directory, or a file ending in .bundle)",
+                                &default,
+                            )
+                        })
+                        .flatten()
+                        .map(PathBuf::from)
+                })
+                .unwrap_or_else(|| PathBuf::from(default));
+            if commit_uncommitted(info) && write_patches(info, &path) {
+                cleanup_worktree(info);
+            }
+        }
+        WorktreeAction::Keep => {
+            eprintln!(
+                "Kept the worktree at {} (branch: {}). Resolve it later with `ajail sessions merge|discard {session_id}`.",
+                info.worktree_path.display(),
+                info.branch_name
+            );
+            return true;
+        }
         WorktreeAction::Discard | WorktreeAction::Prompt => {
             eprintln!("Discarding worktree changes.");
-            cleanup_worktree(info);
+            // Saved first, so a mistaken discard can be undone
+            if options.discard_retention_days == 0 || archive_worktree(info) {
+                cleanup_worktree(info);
+            }
+        }
+    }
+    false
+}
+
+/// Handle post-session overlay cleanup (apply, discard, or prompt).
+/// Returns whether the overlay was kept on purpose.
+pub fn handle_overlay_cleanup(overlay_info: &Option<OverlayInfo>, action: &WorktreeAction) -> bool {
+    let Some(info) = overlay_info else {
+        return false;
+    };
+
+    if !overlay_has_changes(info) {
+        eprintln!("No changes made in overlay.");
+        cleanup_overlay(info);
+        return false;
+    }
+
+    eprintln!("\n--- Overlay changes ---");
+    show_overlay_changes(info);
+
+    let action = match action {
+        WorktreeAction::Prompt => prompt_worktree_action(&OVERLAY_CHOICES),
+        a => *a,
+    };
+
+    match action {
+        WorktreeAction::Merge => {
+            if apply_overlay(info) {
+                cleanup_overlay(info);
+            }
+            // If applying failed, keep the upper layer so nothing is lost
+        }
+        WorktreeAction::Discard | WorktreeAction::Prompt => {
+            eprintln!("Discarding overlay changes.");
+            cleanup_overlay(info);
+        }
+        WorktreeAction::Keep
+        | WorktreeAction::Rebase
+        | WorktreeAction::Squash
+        | WorktreeAction::Branch
+        | WorktreeAction::Patch => {
+            if action != WorktreeAction::Keep
+                && let Some(value) = action.to_possible_value()
+            {
+                eprintln!("`{}` needs a worktree.", value.get_name());
+            }
+            eprintln!("Kept the overlay changes in {}.", info.upper_dir.display());
+            return true;
         }
     }
+    false
 }
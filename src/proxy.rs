@@ -0,0 +1,289 @@
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::os::fd::AsFd;
+use std::os::unix::net::UnixStream;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::util::{receive_fd, send_fd};
+
+/// Hosts reachable through the proxy when the config does not set `proxyAllow`.
+pub const DEFAULT_PROXY_ALLOW: &[&str] = &[
+    "api.anthropic.com",
+    "statsig.anthropic.com",
+    "crates.io",
+    "index.crates.io",
+    "static.crates.io",
+    "registry.npmjs.org",
+];
+
+/// Upper bound on the size of a request head read from the sandbox.
+const MAX_HEAD_LEN: usize = 64 * 1024;
+
+/// Running egress proxy. Records denied destinations for the exit report.
+pub struct EgressProxy {
+    denied: Arc<Mutex<BTreeMap<String, usize>>>,
+}
+
+/// Bind a loopback listener inside the sandbox's network namespace and hand
+/// its fd to the parent over `channel`. Returns the port to advertise in
+/// `HTTP(S)_PROXY`.
+///
+/// Sockets stay bound to the namespace they were created in, so the parent can
+/// accept connections from the sandbox while dialing out from the host network.
+pub fn send_listener(channel: &UnixStream) -> io::Result<u16> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let port = listener.local_addr()?.port();
+
+    send_fd(channel, listener.as_fd())?;
+    Ok(port)
+}
+
+/// Receive the listener sent by `send_listener`. Fails with `UnexpectedEof`
+/// if the sandbox exited before sending it.
+pub fn receive_listener(channel: &UnixStream) -> io::Result<TcpListener> {
+    receive_fd(channel, "proxy listener").map(TcpListener::from)
+}
+
+impl EgressProxy {
+    /// Serve HTTP CONNECT and plain HTTP proxy requests on `listener`,
+    /// forwarding only to hosts matching `allow`.
+    pub fn spawn(listener: TcpListener, allow: Vec<String>) -> Self {
+        let denied = Arc::new(Mutex::new(BTreeMap::new()));
+        let allow = Arc::new(allow);
+
+        let accept_denied = Arc::clone(&denied);
+        thread::spawn(move || {
+            for client in listener.incoming().flatten() {
+                let allow = Arc::clone(&allow);
+                let denied = Arc::clone(&accept_denied);
+                thread::spawn(move || handle_client(client, &allow, &denied));
+            }
+        });
+
+        EgressProxy { denied }
+    }
+
+    /// Print destinations the proxy refused during the session.
+    pub fn report_denied(&self) {
+        let denied = self.denied.lock().unwrap_or_else(|e| e.into_inner());
+        if denied.is_empty() {
+            return;
+        }
+        eprintln!("ajail: proxy denied connections to hosts not in the allowlist:");
+        for (target, count) in denied.iter() {
+            eprintln!("  {target} ({count}x)");
+        }
+    }
+}
+
+/// Whether `host` matches an allowlist entry. Entries are exact hostnames
+/// or `*.example.com`, which matches any subdomain of `example.com`.
+fn host_allowed(host: &str, allow: &[String]) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    allow.iter().any(|pattern| {
+        let pattern = pattern.to_ascii_lowercase();
+        match pattern.strip_prefix("*.") {
+            Some(suffix) => host
+                .strip_suffix(suffix)
+                .is_some_and(|rest| rest.ends_with('.')),
+            None => host == pattern,
+        }
+    })
+}
+
+/// Split `host:port` (or `[v6]:port`) into its parts.
+fn split_authority(authority: &str, default_port: u16) -> Option<(String, u16)> {
+    if let Some(rest) = authority.strip_prefix('[') {
+        let (host, after) = rest.split_once(']')?;
+        let port = match after.strip_prefix(':') {
+            Some(p) => p.parse().ok()?,
+            None => default_port,
+        };
+        return Some((host.to_string(), port));
+    }
+    match authority.rsplit_once(':') {
+        Some((host, port)) => Some((host.to_string(), port.parse().ok()?)),
+        None => Some((authority.to_string(), default_port)),
+    }
+}
+
+/// Read from `stream` until the end of the HTTP request head.
+/// Returns the head and any bytes read past it.
+fn read_head(stream: &mut TcpStream) -> io::Result<(String, Vec<u8>)> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    loop {
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            let rest = buf.split_off(pos + 4);
+            return Ok((String::from_utf8_lossy(&buf).into_owned(), rest));
+        }
+        if buf.len() > MAX_HEAD_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "request head too large",
+            ));
+        }
+        let n = stream.read(&mut chunk)?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+}
+
+fn respond(client: &mut TcpStream, status: &str) {
+    let _ = write!(
+        client,
+        "HTTP/1.1 {status}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+    );
+}
+
+fn handle_client(mut client: TcpStream, allow: &[String], denied: &Mutex<BTreeMap<String, usize>>) {
+    let Ok((head, rest)) = read_head(&mut client) else {
+        return;
+    };
+    let mut lines = head.split("\r\n");
+    let request_line = lines.next().unwrap_or_default();
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target), Some(version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        respond(&mut client, "400 Bad Request");
+        return;
+    };
+
+    // CONNECT carries host:port; plain HTTP proxying carries an absolute URI.
+    let (authority, path) = if method.eq_ignore_ascii_case("CONNECT") {
+        (target, None)
+    } else if let Some(rest) = target.strip_prefix("http://") {
+        match rest.find('/') {
+            Some(i) => (&rest[..i], Some(&rest[i..])),
+            None => (rest, Some("/")),
+        }
+    } else {
+        respond(&mut client, "400 Bad Request");
+        return;
+    };
+
+    let default_port = if path.is_some() { 80 } else { 443 };
+    let Some((host, port)) = split_authority(authority, default_port) else {
+        respond(&mut client, "400 Bad Request");
+        return;
+    };
+
+    if !host_allowed(&host, allow) {
+        let mut denied = denied.lock().unwrap_or_else(|e| e.into_inner());
+        *denied.entry(format!("{host}:{port}")).or_default() += 1;
+        respond(&mut client, "403 Forbidden");
+        return;
+    }
+
+    let request = match path {
+        Some(path) => match origin_request(method, path, version, authority, lines) {
+            Ok(request) => Some(request),
+            Err(status) => {
+                respond(&mut client, status);
+                return;
+            }
+        },
+        None => None,
+    };
+
+    let mut upstream = match TcpStream::connect((host.as_str(), port)) {
+        Ok(s) => s,
+        Err(_) => {
+            respond(&mut client, "502 Bad Gateway");
+            return;
+        }
+    };
+
+    match request {
+        None => {
+            let _ = client.write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n");
+            if upstream.write_all(&rest).is_ok() {
+                relay(client, upstream);
+            }
+        }
+        Some((head, body_len)) => forward_request(client, upstream, &head, &rest, body_len),
+    }
+}
+
+/// Rewrite a proxied request to origin-form for the upstream server, and
+/// return it with the length of its body. The request gets `Connection:
+/// close` and a `Host` matching the checked authority, so the connection
+/// carries this one request only; the next one comes in on a new connection
+/// and is checked again.
+fn origin_request<'a>(
+    method: &str,
+    path: &str,
+    version: &str,
+    authority: &str,
+    headers: impl Iterator<Item = &'a str>,
+) -> Result<(String, u64), &'static str> {
+    let mut head = format!("{method} {path} {version}\r\nHost: {authority}\r\n");
+    let mut body_len = 0;
+    for line in headers.filter(|l| !l.is_empty()) {
+        let name = line.split(':').next().unwrap_or_default().trim();
+        let name = name.to_ascii_lowercase();
+        if name.starts_with("proxy-")
+            || matches!(name.as_str(), "host" | "connection" | "keep-alive")
+        {
+            continue;
+        }
+        // Without a length the end of the request is only found by parsing
+        // the chunks, which the proxy does not do.
+        if name == "transfer-encoding" {
+            return Err("411 Length Required");
+        }
+        if name == "content-length" {
+            let value = line.split_once(':').map_or("", |(_, v)| v.trim());
+            body_len = value.parse().map_err(|_| "400 Bad Request")?;
+        }
+        head.push_str(line);
+        head.push_str("\r\n");
+    }
+    head.push_str("Connection: close\r\n\r\n");
+    Ok((head, body_len))
+}
+
+/// Send one request of `head` and a `body_len` byte body, partly read
+/// already into `rest`, then relay the response. Anything the client sends
+/// past the body is not forwarded.
+fn forward_request(
+    mut client: TcpStream,
+    mut upstream: TcpStream,
+    head: &str,
+    rest: &[u8],
+    body_len: u64,
+) {
+    let buffered = rest
+        .len()
+        .min(usize::try_from(body_len).unwrap_or(usize::MAX));
+    let remaining = body_len - buffered as u64;
+    let sent = upstream
+        .write_all(head.as_bytes())
+        .and_then(|()| upstream.write_all(&rest[..buffered]))
+        .and_then(|()| io::copy(&mut (&client).take(remaining), &mut upstream).map(drop));
+    if sent.is_ok() {
+        let _ = io::copy(&mut upstream, &mut client);
+    }
+    let _ = client.shutdown(Shutdown::Both);
+}
+
+/// Copy bytes in both directions until either side closes.
+fn relay(client: TcpStream, upstream: TcpStream) {
+    let (Ok(mut client_read), Ok(mut upstream_write)) = (client.try_clone(), upstream.try_clone())
+    else {
+        return;
+    };
+    let uplink = thread::spawn(move || {
+        let _ = io::copy(&mut client_read, &mut upstream_write);
+        let _ = upstream_write.shutdown(Shutdown::Write);
+    });
+
+    let (mut upstream_read, mut client_write) = (upstream, client);
+    let _ = io::copy(&mut upstream_read, &mut client_write);
+    let _ = client_write.shutdown(Shutdown::Write);
+    let _ = uplink.join();
+}
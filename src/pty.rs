@@ -0,0 +1,237 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::fd::{AsRawFd, BorrowedFd, OwnedFd, RawFd};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use nix::errno::Errno;
+use nix::pty::{Winsize, openpty};
+use nix::sys::signal::{SigSet, Signal};
+use nix::sys::termios::{SetArg, Termios, cfmakeraw, tcgetattr, tcsetattr};
+use nix::unistd::{dup2, isatty, pipe, read, setsid};
+
+use crate::record::Recording;
+
+/// The host terminal's original modes while the proxy has it in raw mode.
+///
+/// Also serializes reads from the host terminal: the input relay holds it
+/// for each read, and `with_host_terminal` for a whole prompt.
+static HOST_MODES: Mutex<Option<Termios>> = Mutex::new(None);
+
+/// A pseudo-terminal for the sandboxed command, before the fork.
+pub struct Pty {
+    master: OwnedFd,
+    slave: OwnedFd,
+    size: Winsize,
+}
+
+/// Running relay between the host terminal and the pty master.
+pub struct PtyProxy {
+    output: JoinHandle<()>,
+    input: JoinHandle<()>,
+    /// Closing it stops the input relay
+    stop: OwnedFd,
+}
+
+fn stdin() -> BorrowedFd<'static> {
+    // Safety: stdin stays open for the life of the process.
+    unsafe { BorrowedFd::borrow_raw(libc::STDIN_FILENO) }
+}
+
+/// Open a pty with the host terminal's size and modes, or `None` when
+/// ajail does not run on a terminal.
+pub fn open() -> io::Result<Option<Pty>> {
+    if !isatty(libc::STDIN_FILENO).unwrap_or(false) || !isatty(libc::STDOUT_FILENO).unwrap_or(false)
+    {
+        return Ok(None);
+    }
+    let modes = tcgetattr(stdin())?;
+    let size = window_size(stdin())?;
+    let pty = openpty(&size, &modes)?;
+    Ok(Some(Pty {
+        master: pty.master,
+        slave: pty.slave,
+        size,
+    }))
+}
+
+fn window_size(fd: BorrowedFd) -> io::Result<Winsize> {
+    let mut size: Winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(fd.as_raw_fd(), libc::TIOCGWINSZ, &mut size) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(size)
+}
+
+impl Pty {
+    /// Columns and rows the terminal started with.
+    pub fn size(&self) -> (u16, u16) {
+        (self.size.ws_col, self.size.ws_row)
+    }
+
+    /// In the child: start a new session with the pty as its controlling
+    /// terminal and the standard streams on it. stderr stays put when it
+    /// does not go to the terminal.
+    pub fn attach(self) -> io::Result<()> {
+        drop(self.master);
+        setsid()?;
+        if unsafe { libc::ioctl(self.slave.as_raw_fd(), libc::TIOCSCTTY, 0) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut streams: Vec<RawFd> = vec![libc::STDIN_FILENO, libc::STDOUT_FILENO];
+        if isatty(libc::STDERR_FILENO).unwrap_or(false) {
+            streams.push(libc::STDERR_FILENO);
+        }
+        for fd in streams {
+            dup2(self.slave.as_raw_fd(), fd)?;
+        }
+        Ok(())
+    }
+
+    /// In the parent: put the host terminal in raw mode and relay between
+    /// it and the pty until the sandbox closes its end. Output and resizes
+    /// also go to `recording`, if set.
+    pub fn spawn_proxy(self, recording: Option<Recording>) -> io::Result<PtyProxy> {
+        // The child holds the slave now; keeping a copy here would stop the
+        // master from seeing the hangup when the sandbox exits.
+        drop(self.slave);
+        let master = File::from(self.master);
+
+        // Handled by a thread with sigwait; threads spawned from here on
+        // inherit the mask.
+        let mut winch = SigSet::empty();
+        winch.add(Signal::SIGWINCH);
+        winch.thread_block()?;
+
+        let modes = tcgetattr(stdin())?;
+        let mut raw = modes.clone();
+        cfmakeraw(&mut raw);
+        tcsetattr(stdin(), SetArg::TCSADRAIN, &raw)?;
+        *HOST_MODES.lock().unwrap_or_else(|e| e.into_inner()) = Some(modes);
+
+        let recording = recording.map(|r| Arc::new(Mutex::new(r)));
+
+        let resize = master.try_clone()?;
+        let resize_recording = recording.clone();
+        thread::spawn(move || {
+            while winch.wait().is_ok() {
+                if let Ok(size) = window_size(stdin()) {
+                    unsafe { libc::ioctl(resize.as_raw_fd(), libc::TIOCSWINSZ, &size) };
+                    if let Some(ref recording) = resize_recording {
+                        let mut recording = recording.lock().unwrap_or_else(|e| e.into_inner());
+                        recording.resize((size.ws_col, size.ws_row));
+                    }
+                }
+            }
+        });
+
+        let (stop_read, stop) = pipe()?;
+        let to_master = master.try_clone()?;
+        let input = thread::spawn(move || relay_input(to_master, stop_read));
+        let output = thread::spawn(move || relay_output(master, recording));
+
+        Ok(PtyProxy {
+            output,
+            input,
+            stop,
+        })
+    }
+}
+
+impl PtyProxy {
+    /// Wait for the sandbox's remaining output, stop reading the host
+    /// terminal and restore its modes.
+    pub fn finish(self) {
+        let _ = self.output.join();
+        drop(self.stop);
+        let _ = self.input.join();
+        if let Some(modes) = HOST_MODES.lock().unwrap_or_else(|e| e.into_inner()).take() {
+            let _ = tcsetattr(stdin(), SetArg::TCSADRAIN, &modes);
+        }
+    }
+}
+
+/// Run `f` with the host terminal in its original modes and the input relay
+/// paused, so a prompt from ajail gets the user's answer.
+pub fn with_host_terminal<T>(f: impl FnOnce() -> T) -> T {
+    let modes = HOST_MODES.lock().unwrap_or_else(|e| e.into_inner());
+    let raw = modes.as_ref().and_then(|modes| {
+        let raw = tcgetattr(stdin()).ok()?;
+        tcsetattr(stdin(), SetArg::TCSADRAIN, modes).ok()?;
+        Some(raw)
+    });
+    let result = f();
+    if let Some(raw) = raw {
+        let _ = tcsetattr(stdin(), SetArg::TCSADRAIN, &raw);
+    }
+    result
+}
+
+/// Copy keystrokes from the host terminal to the pty until `stop` closes.
+fn relay_input(mut master: File, stop: OwnedFd) {
+    let mut buf = [0u8; 4096];
+    loop {
+        let mut fds = [
+            libc::pollfd {
+                fd: libc::STDIN_FILENO,
+                events: libc::POLLIN,
+                revents: 0,
+            },
+            libc::pollfd {
+                fd: stop.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            },
+        ];
+        if unsafe { libc::poll(fds.as_mut_ptr(), 2, -1) } < 0 {
+            if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return;
+        }
+        if fds[1].revents != 0 {
+            return;
+        }
+
+        // A prompt may have taken the input while we waited for the lock.
+        let _modes = HOST_MODES.lock().unwrap_or_else(|e| e.into_inner());
+        fds[0].revents = 0;
+        if unsafe { libc::poll(fds.as_mut_ptr(), 1, 0) } <= 0 || fds[0].revents == 0 {
+            continue;
+        }
+        // Unbuffered, so nothing is held back from the next prompt.
+        match read(libc::STDIN_FILENO, &mut buf) {
+            Ok(0) => return,
+            Ok(n) => {
+                if master.write_all(&buf[..n]).is_err() {
+                    return;
+                }
+            }
+            Err(Errno::EINTR) => {}
+            Err(_) => return,
+        }
+    }
+}
+
+/// Copy the sandbox's terminal output to stdout until every slave fd is
+/// closed, which the master reports as EIO.
+fn relay_output(mut master: File, recording: Option<Arc<Mutex<Recording>>>) {
+    let mut buf = [0u8; 4096];
+    let mut stdout = io::stdout();
+    loop {
+        match master.read(&mut buf) {
+            Ok(0) => return,
+            Ok(n) => {
+                if let Some(ref recording) = recording {
+                    let mut recording = recording.lock().unwrap_or_else(|e| e.into_inner());
+                    recording.output(&buf[..n]);
+                }
+                if stdout.write_all(&buf[..n]).is_err() || stdout.flush().is_err() {
+                    return;
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(_) => return,
+        }
+    }
+}
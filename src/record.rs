@@ -0,0 +1,147 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use serde_json::json;
+
+use crate::config::Options;
+use crate::session::sessions_dir;
+
+/// A terminal session being written as an asciicast v2 file: a JSON header
+/// line, then one `[time, code, data]` line per event.
+pub struct Recording {
+    file: BufWriter<File>,
+    path: PathBuf,
+    start: Instant,
+    /// Trailing bytes of a UTF-8 sequence split across reads
+    partial: Vec<u8>,
+}
+
+/// The asciicast header line, with the standard fields first.
+#[derive(Serialize)]
+struct Header {
+    version: u8,
+    width: u16,
+    height: u16,
+    timestamp: u64,
+    command: String,
+    title: String,
+    env: serde_json::Value,
+    ajail: serde_json::Value,
+}
+
+/// Where `recordSessions` puts the recording of session `session_id`.
+pub fn session_recording_path(session_id: &str) -> PathBuf {
+    sessions_dir().join(format!("{session_id}.cast"))
+}
+
+impl Recording {
+    /// Create the file up front, so a bad path fails before the session
+    /// starts. Recordings can hold secrets shown on screen, so only the user
+    /// can read them.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)?;
+        Ok(Recording {
+            file: BufWriter::new(file),
+            path: path.to_path_buf(),
+            start: Instant::now(),
+            partial: Vec::new(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Remove the file of a session that will not be recorded after all.
+    pub fn discard(self) {
+        let _ = fs::remove_file(&self.path);
+    }
+
+    /// Write the header and start the clock. Besides the standard fields,
+    /// `ajail` holds the session id and the sandbox options.
+    pub fn start(
+        &mut self,
+        (width, height): (u16, u16),
+        session_id: &str,
+        args: &[String],
+        options: &Options,
+    ) -> io::Result<()> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let header = Header {
+            version: 2,
+            width,
+            height,
+            timestamp,
+            command: args.join(" "),
+            title: format!("ajail session {session_id}"),
+            env: json!({
+                "TERM": env::var("TERM").unwrap_or_default(),
+                "SHELL": env::var("SHELL").unwrap_or_default(),
+            }),
+            ajail: json!({
+                "sessionId": session_id,
+                "options": options,
+            }),
+        };
+        let header = serde_json::to_string(&header)?;
+        writeln!(self.file, "{header}")?;
+        self.file.flush()?;
+        self.start = Instant::now();
+        Ok(())
+    }
+
+    /// Record terminal output.
+    pub fn output(&mut self, data: &[u8]) {
+        self.partial.extend_from_slice(data);
+        let complete = self.partial.len() - incomplete_tail(&self.partial);
+        let text = String::from_utf8_lossy(&self.partial[..complete]).into_owned();
+        self.partial.drain(..complete);
+        if !text.is_empty() {
+            self.event("o", &text);
+        }
+    }
+
+    /// Record a terminal resize.
+    pub fn resize(&mut self, (width, height): (u16, u16)) {
+        self.event("r", &format!("{width}x{height}"));
+    }
+
+    fn event(&mut self, code: &str, data: &str) {
+        // Microsecond precision, as asciinema writes it
+        let time = (self.start.elapsed().as_secs_f64() * 1e6).round() / 1e6;
+        // Flushed per event, so the recording survives ajail being killed
+        let _ = writeln!(self.file, "{}", json!([time, code, data]));
+        let _ = self.file.flush();
+    }
+}
+
+/// Length of an unfinished UTF-8 sequence at the end of `bytes`.
+fn incomplete_tail(bytes: &[u8]) -> usize {
+    for (back, &byte) in bytes.iter().rev().take(3).enumerate() {
+        let have = back + 1;
+        let needed = match byte {
+            0x00..=0x7F => return 0,
+            0x80..=0xBF => continue,
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            _ => 4,
+        };
+        return if needed > have { have } else { 0 };
+    }
+    0
+}
@@ -4,10 +4,13 @@ use std::path::{Path, PathBuf};
 
 use nix::mount::{MsFlags, mount};
 use nix::sched::{CloneFlags, unshare};
-use nix::sys::wait::{WaitStatus, waitpid};
-use nix::unistd::{ForkResult, fork, getgid, getuid};
+use nix::unistd::{getgid, getuid};
 
+use crate::NetworkMode;
 use crate::config::Options;
+use crate::dangerous;
+use crate::init;
+use crate::overlay::OverlayInfo;
 
 pub struct SandboxConfig {
     pub home: PathBuf,
@@ -21,6 +24,10 @@ pub struct SandboxConfig {
     /// When using worktrees, the original repo's .git dir must be accessible
     /// so the worktree's .git file can reference it.
     pub original_git_dir: Option<PathBuf>,
+    /// In overlay mode, the repo is mounted copy-on-write over these layers.
+    pub overlay: Option<OverlayInfo>,
+    /// Placeholders for missing dangerous paths, masked inside the sandbox.
+    pub placeholders: Vec<PathBuf>,
     pub options: Options,
 }
 
@@ -29,6 +36,15 @@ pub struct SandboxConfig {
 struct PathLocations {
     repo_under_tmp: bool,
     share_tree_under_tmp: bool,
+    /// User bind mounts with a destination under /tmp, mounted by `isolate_tmp`.
+    pending_binds: Vec<PendingBind>,
+}
+
+/// A user bind mount whose source may already have been staged.
+struct PendingBind {
+    src: PathBuf,
+    dst: PathBuf,
+    readonly: bool,
 }
 
 /// Bind-mount `src` onto `dst`. Creates `dst` if needed.
@@ -119,12 +135,17 @@ fn mount_tmpfs(dst: &Path) -> nix::Result<()> {
 }
 
 /// Create new user and mount namespaces, write UID/GID mappings,
-/// and make all mounts private.
-fn init_namespaces() -> nix::Result<()> {
+/// and make all mounts private. Unless the host network is shared,
+/// also create a fresh network namespace.
+fn init_namespaces(options: &Options) -> nix::Result<()> {
     let uid = getuid();
     let gid = getgid();
 
-    unshare(CloneFlags::CLONE_NEWUSER | CloneFlags::CLONE_NEWNS | CloneFlags::CLONE_NEWPID)?;
+    let mut flags = CloneFlags::CLONE_NEWUSER | CloneFlags::CLONE_NEWNS | CloneFlags::CLONE_NEWPID;
+    if options.network != NetworkMode::Host {
+        flags |= CloneFlags::CLONE_NEWNET;
+    }
+    unshare(flags)?;
 
     fs::write("/proc/self/setgroups", "deny").ok();
     fs::write("/proc/self/uid_map", format!("{uid} {uid} 1\n"))
@@ -141,6 +162,53 @@ fn init_namespaces() -> nix::Result<()> {
     )
 }
 
+/// Bring up the loopback interface in a fresh network namespace.
+/// New network namespaces start with `lo` present but down.
+fn bring_up_loopback() -> nix::Result<()> {
+    let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(nix::errno::Errno::last());
+    }
+
+    let mut ifr: libc::ifreq = unsafe { std::mem::zeroed() };
+    for (dst, src) in ifr.ifr_name.iter_mut().zip(b"lo\0") {
+        *dst = *src as libc::c_char;
+    }
+
+    let result = (|| {
+        if unsafe { libc::ioctl(fd, libc::SIOCGIFFLAGS, &mut ifr) } < 0 {
+            return Err(nix::errno::Errno::last());
+        }
+        unsafe { ifr.ifr_ifru.ifru_flags |= (libc::IFF_UP | libc::IFF_RUNNING) as libc::c_short };
+        if unsafe { libc::ioctl(fd, libc::SIOCSIFFLAGS, &ifr) } < 0 {
+            return Err(nix::errno::Errno::last());
+        }
+        Ok(())
+    })();
+
+    unsafe { libc::close(fd) };
+    result
+}
+
+/// Mount the repo as an overlayfs with the real tree as the lower layer, so
+/// writes land in the session's upper layer. Runs before `isolate_home`, whose
+/// recursive bind mounts then carry the overlay into the new home.
+fn mount_repo_overlay(repo_root: &Path, overlay: &OverlayInfo) -> nix::Result<()> {
+    let options = format!(
+        "lowerdir={},upperdir={},workdir={},userxattr",
+        repo_root.display(),
+        overlay.upper_dir.display(),
+        overlay.work_dir.display()
+    );
+    mount(
+        Some("overlay"),
+        repo_root,
+        Some("overlay"),
+        MsFlags::empty(),
+        Some(options.as_str()),
+    )
+}
+
 /// Collect $PATH directories under $HOME that need preserving.
 ///
 /// Returns two lists:
@@ -267,6 +335,30 @@ fn isolate_home(
         bind_mount(original, stage, true)?;
     }
 
+    // Stage user bind mount sources under $HOME
+    let mut user_binds = Vec::new();
+    for (i, m) in config.options.bind_mounts.iter().enumerate() {
+        if !m.src.exists() {
+            eprintln!(
+                "ajail: bind mount source does not exist, skipping: {}",
+                m.src.display()
+            );
+            continue;
+        }
+        let src = if m.src.starts_with(&config.home) {
+            let stage = staging.join(format!("bind-{i}"));
+            bind_mount(&m.src, &stage, m.readonly)?;
+            stage
+        } else {
+            m.src.clone()
+        };
+        user_binds.push(PendingBind {
+            src,
+            dst: m.dst.clone(),
+            readonly: m.readonly,
+        });
+    }
+
     // Mount tmpfs over $HOME to hide real home
     mount_tmpfs(&config.home)?;
 
@@ -311,22 +403,59 @@ fn isolate_home(
         }
     }
 
+    // User bind mounts go on top of the restored tree. Destinations under
+    // /tmp would be hidden by the /tmp overlay, so leave those to `isolate_tmp`.
+    let mut pending_binds = Vec::new();
+    for bind in user_binds {
+        if bind.dst.starts_with(tmp_path) {
+            pending_binds.push(bind);
+        } else {
+            user_bind_mount(&bind)?;
+        }
+    }
+
     Ok(PathLocations {
         repo_under_tmp,
         share_tree_under_tmp,
+        pending_binds,
+    })
+}
+
+/// Mount a user-requested bind, reporting which one failed.
+fn user_bind_mount(bind: &PendingBind) -> nix::Result<()> {
+    bind_mount(&bind.src, &bind.dst, bind.readonly).inspect_err(|e| {
+        eprintln!(
+            "ajail: failed to bind-mount {} at {}: {e}",
+            bind.src.display(),
+            bind.dst.display()
+        );
     })
 }
 
-/// Overlay /tmp with tmpfs. If the repo or share_tree live under /tmp,
-/// stage them to $HOME first, overlay, then restore.
+/// Overlay /tmp with tmpfs. If the repo, share_tree or a pending user bind
+/// source live under /tmp, stage them to $HOME first, overlay, then restore.
 fn isolate_tmp(config: &SandboxConfig, locs: &PathLocations) -> nix::Result<()> {
     let tmp_path = Path::new("/tmp");
 
-    if locs.repo_under_tmp || locs.share_tree_under_tmp {
-        let staging2 = config.home.join(".ajail-staging");
+    let staging2 = config.home.join(".ajail-staging");
+    let stage2_repo = staging2.join("repo");
+    let stage2_share_tree = staging2.join("share-tree");
+    let stage2_binds: Vec<Option<PathBuf>> = locs
+        .pending_binds
+        .iter()
+        .enumerate()
+        .map(|(i, b)| {
+            b.src
+                .starts_with(tmp_path)
+                .then(|| staging2.join(format!("bind-{i}")))
+        })
+        .collect();
+    let need_staging = locs.repo_under_tmp
+        || locs.share_tree_under_tmp
+        || stage2_binds.iter().any(Option::is_some);
+
+    if need_staging {
         fs::create_dir_all(&staging2).ok();
-        let stage2_repo = staging2.join("repo");
-        let stage2_share_tree = staging2.join("share-tree");
 
         if locs.repo_under_tmp {
             bind_mount(&config.repo_root, &stage2_repo, false)?;
@@ -334,31 +463,75 @@ fn isolate_tmp(config: &SandboxConfig, locs: &PathLocations) -> nix::Result<()>
         if locs.share_tree_under_tmp {
             bind_mount(&config.share_tree, &stage2_share_tree, true)?;
         }
+        for (bind, stage) in locs.pending_binds.iter().zip(&stage2_binds) {
+            if let Some(stage) = stage {
+                bind_mount(&bind.src, stage, bind.readonly)?;
+            }
+        }
+    }
 
-        mount_tmpfs(tmp_path)?;
+    mount_tmpfs(tmp_path)?;
 
-        if locs.share_tree_under_tmp {
-            bind_mount(&stage2_share_tree, &config.share_tree, true)?;
-        }
-        if locs.repo_under_tmp {
-            bind_mount(&stage2_repo, &config.repo_root, false)?;
-        }
+    if locs.share_tree_under_tmp {
+        bind_mount(&stage2_share_tree, &config.share_tree, true)?;
+    }
+    if locs.repo_under_tmp {
+        bind_mount(&stage2_repo, &config.repo_root, false)?;
+    }
+    for (bind, stage) in locs.pending_binds.iter().zip(&stage2_binds) {
+        let src = stage.clone().unwrap_or_else(|| bind.src.clone());
+        user_bind_mount(&PendingBind {
+            src,
+            dst: bind.dst.clone(),
+            readonly: bind.readonly,
+        })?;
+    }
 
-        // Clean up staging
+    // Clean up staging
+    if need_staging {
         if locs.repo_under_tmp {
             nix::mount::umount(&stage2_repo).ok();
         }
         if locs.share_tree_under_tmp {
             nix::mount::umount(&stage2_share_tree).ok();
         }
+        for stage in stage2_binds.iter().flatten() {
+            nix::mount::umount(stage).ok();
+        }
         fs::remove_dir_all(&staging2).ok();
-    } else {
-        mount_tmpfs(tmp_path)?;
     }
 
     Ok(())
 }
 
+/// Mount the user-requested empty tmpfs directories.
+fn mount_user_tmpfs(options: &Options) -> nix::Result<()> {
+    for path in &options.tmpfs_mounts {
+        mount_tmpfs(path).inspect_err(|e| {
+            eprintln!("ajail: failed to mount tmpfs at {}: {e}", path.display());
+        })?;
+    }
+    Ok(())
+}
+
+/// Mask user-requested paths: directories with an empty read-only tmpfs,
+/// files with /dev/null. Applied last so it covers every other mount.
+fn hide_user_paths(options: &Options) -> nix::Result<()> {
+    for path in &options.hidden_paths {
+        let result = if path.is_dir() {
+            mount_empty_dir(path)
+        } else if path.exists() {
+            bind_mount(Path::new("/dev/null"), path, false)
+        } else {
+            continue;
+        };
+        result.inspect_err(|e| {
+            eprintln!("ajail: failed to hide {}: {e}", path.display());
+        })?;
+    }
+    Ok(())
+}
+
 /// Bind-mount agent sockets (SSH, GPG) or the full XDG runtime directory.
 fn mount_agent_sockets(options: &Options) -> nix::Result<()> {
     let uid = getuid();
@@ -409,57 +582,40 @@ fn hide_docker_socket(options: &Options) {
     }
 }
 
-/// Known dangerous files that should be read-only inside the sandbox.
-const DANGEROUS_FILES: &[&str] = &[
-    ".bashrc",
-    ".zshrc",
-    ".bash_profile",
-    ".zprofile",
-    ".profile",
-    ".gitconfig",
-    ".gitmodules",
-    ".ripgreprc",
-    ".mcp.json",
-    ".git/config",
-];
-
-/// Known dangerous directories that should be read-only inside the sandbox.
-const DANGEROUS_DIRS: &[&str] = &[
-    ".git/hooks",
-    ".vscode",
-    ".idea",
-    ".zed",
-    ".claude/commands",
-    ".claude/agents",
-];
-
-/// Bind-mount known dangerous files and directories read-only over themselves
+/// Mount an empty read-only tmpfs over the directory `path`.
+fn mount_empty_dir(path: &Path) -> nix::Result<()> {
+    mount(
+        Some("tmpfs"),
+        path,
+        Some("tmpfs"),
+        MsFlags::MS_NOSUID | MsFlags::MS_NODEV | MsFlags::MS_RDONLY,
+        None::<&str>,
+    )
+}
+
+/// Bind-mount dangerous files and directories read-only over themselves
 /// to prevent the sandboxed process from modifying them.
-/// Only protects paths that already exist.
+/// Patterns are expanded against the repo tree; only existing paths are
+/// protected. Placeholders created by `dangerous::create_placeholders` are
+/// masked with an empty read-only directory instead, so nothing can be
+/// created in or through them.
 fn protect_dangerous_files(config: &SandboxConfig) -> nix::Result<()> {
     if config.options.allow_dangerous_writes {
         return Ok(());
     }
 
-    let repo = &config.repo_root;
-
-    for name in DANGEROUS_FILES {
-        let path = repo.join(name);
-        if path.exists()
-            && let Err(e) = bind_mount(&path, &path, true)
-        {
-            eprintln!(
-                "ajail: failed to protect {}, leaving writable: {e}",
-                path.display()
-            );
-        }
-    }
-
-    for name in DANGEROUS_DIRS {
-        let path = repo.join(name);
-        if path.is_dir()
-            && let Err(e) = bind_mount(&path, &path, true)
-        {
+    let dangerous = dangerous::find_dangerous_paths(
+        &config.repo_root,
+        config.original_git_dir.as_deref(),
+        &config.options,
+    );
+    for path in dangerous {
+        let result = if config.placeholders.contains(&path) {
+            mount_empty_dir(&path)
+        } else {
+            bind_mount(&path, &path, true)
+        };
+        if let Err(e) = result {
             eprintln!(
                 "ajail: failed to protect {}, leaving writable: {e}",
                 path.display()
@@ -481,49 +637,36 @@ fn mount_proc() -> nix::Result<()> {
     )
 }
 
-/// Wait for a child process, returning its exit code. Minimal inline
-/// version to avoid circular dependency with process.rs.
-fn wait_and_exit(pid: nix::unistd::Pid) -> ! {
-    let code = loop {
-        match waitpid(pid, None) {
-            Ok(WaitStatus::Exited(_, code)) => break code,
-            Ok(WaitStatus::Signaled(_, sig, _)) => break 128 + sig as i32,
-            Ok(_) => continue,
-            Err(nix::errno::Errno::EINTR) => continue,
-            Err(e) => {
-                eprintln!("waitpid error: {e}");
-                break 1;
-            }
-        }
-    };
-    std::process::exit(code);
-}
-
 pub fn setup_namespace(config: &SandboxConfig) -> nix::Result<()> {
-    init_namespaces()?;
+    init_namespaces(&config.options)?;
+    if config.options.network != NetworkMode::Host {
+        bring_up_loopback()?;
+    }
 
     // CLONE_NEWPID only takes effect for children, so fork here.
-    // The grandchild becomes PID 1 in the new PID namespace.
-    // The middle process waits and exits with the grandchild's status.
-    match unsafe { fork() } {
-        Ok(ForkResult::Parent { child }) => {
-            wait_and_exit(child);
-        }
-        Ok(ForkResult::Child) => {
-            // We are PID 1 in the new PID namespace
-        }
-        Err(e) => return Err(e),
-    }
+    // The child becomes PID 1 in the new PID namespace; this process
+    // forwards signals to it and exits with its status.
+    init::fork_supervised()?;
 
     mount_proc()?;
 
+    if let Some(ref overlay) = config.overlay {
+        mount_repo_overlay(&config.repo_root, overlay)?;
+    }
+
     let (path_dirs_outside, path_dirs_under_home) = collect_home_path_dirs(&config.home);
 
     let locs = isolate_home(config, &path_dirs_outside, &path_dirs_under_home)?;
     isolate_tmp(config, &locs)?;
+    mount_user_tmpfs(&config.options)?;
     mount_agent_sockets(&config.options)?;
     hide_docker_socket(&config.options);
     protect_dangerous_files(config)?;
+    hide_user_paths(&config.options)?;
+
+    // PID 1 stays behind as the namespace's init; the command runs as its
+    // child.
+    init::fork_supervised()?;
 
     Ok(())
 }
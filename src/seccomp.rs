@@ -1,82 +1,707 @@
 use std::io;
+use std::os::fd::{FromRawFd, OwnedFd};
 
-/// Install a seccomp BPF filter that blocks `socket(AF_UNIX, ...)` with EACCES.
+use serde::{Deserialize, Serialize};
+
+use crate::config::Options;
+use crate::syscalls;
+
+const AUDIT_ARCH_X86_64: u32 = 0xC000_003E;
+const AUDIT_ARCH_AARCH64: u32 = 0xC000_00B7;
+const AUDIT_ARCH_I386: u32 = 0x4000_0003;
+const AUDIT_ARCH_ARM: u32 = 0x4000_0028;
+
+/// Set in the syscall number of x32 ABI calls, which share AUDIT_ARCH_X86_64.
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+/// Syscall number -1, which a tracer sets to skip a call. The kernel runs the
+/// filter again on it, and nothing runs if it is allowed.
+const SKIPPED_SYSCALL: u32 = u32::MAX;
+
+/// `socketcall` multiplexer call number for `socket()` (linux/net.h)
+const SYS_SOCKET: u64 = 1;
+
+/// Terminal ioctls that inject input (asm-generic/ioctls.h)
+const TIOCSTI: u64 = 0x5412;
+const TIOCLINUX: u64 = 0x541C;
+
+/// ptrace requests that attach to an existing process (linux/ptrace.h)
+const PTRACE_ATTACH: u64 = 16;
+const PTRACE_SEIZE: u64 = 0x4206;
+
+/// Syscalls denied by the hardening profile, in the spirit of Docker's
+/// default seccomp profile: kernel attack surface an agent has no use for
+/// (eBPF, perf, userfaultfd, keyrings), module loading, kexec, raw I/O port
+/// access, and file handle lookups that sidestep mount restrictions.
+const HARDENING_DENY: &[&str] = &[
+    "bpf",
+    "perf_event_open",
+    "userfaultfd",
+    "keyctl",
+    "add_key",
+    "request_key",
+    "kexec_load",
+    "kexec_file_load",
+    "open_by_handle_at",
+    "init_module",
+    "finit_module",
+    "delete_module",
+    "create_module",
+    "acct",
+    "swapon",
+    "swapoff",
+    "reboot",
+    "syslog",
+    "iopl",
+    "ioperm",
+    "vm86",
+    "vm86old",
+    "lookup_dcookie",
+    "quotactl",
+    "quotactl_fd",
+    "nfsservctl",
+    "_sysctl",
+    "uselib",
+];
+
+/// Syscalls the init supervising the sandbox needs once it is locked down:
+/// waiting for signals, passing them on, reaping, reporting and exiting.
+const INIT_ALLOW: &[&str] = &[
+    "rt_sigtimedwait",
+    "rt_sigprocmask",
+    "rt_sigreturn",
+    "wait4",
+    "waitid",
+    "kill",
+    "write",
+    "close",
+    "brk",
+    "mmap",
+    "munmap",
+    "mremap",
+    "madvise",
+    "futex",
+    "getpid",
+    "gettid",
+    "tgkill",
+    "exit",
+    "exit_group",
+];
+
+const SECCOMP_RET_KILL_PROCESS: u32 = 0x8000_0000;
+const SECCOMP_RET_ERRNO: u32 = 0x0005_0000;
+const SECCOMP_RET_USER_NOTIF: u32 = 0x7FC0_0000;
+const SECCOMP_RET_LOG: u32 = 0x7FFC_0000;
+const SECCOMP_RET_ALLOW: u32 = 0x7FFF_0000;
+
+// BPF instruction codes (pre-combined)
+const BPF_LD_W_ABS: u16 = 0x20; // BPF_LD | BPF_W | BPF_ABS
+const BPF_ALU_AND_K: u16 = 0x54; // BPF_ALU | BPF_AND | BPF_K
+const BPF_JMP_JA: u16 = 0x05; // BPF_JMP | BPF_JA
+const BPF_JMP_JEQ_K: u16 = 0x15; // BPF_JMP | BPF_JEQ | BPF_K
+const BPF_JMP_JGT_K: u16 = 0x25; // BPF_JMP | BPF_JGT | BPF_K
+const BPF_JMP_JGE_K: u16 = 0x35; // BPF_JMP | BPF_JGE | BPF_K
+const BPF_RET_K: u16 = 0x06; // BPF_RET | BPF_K
+
+/// Kernel limit on the length of a BPF program.
+const BPF_MAXINSNS: usize = 4096;
+
+// seccomp_data field offsets
+const OFF_NR: u32 = 0; // offsetof(seccomp_data, nr)
+const OFF_ARCH: u32 = 4; // offsetof(seccomp_data, arch)
+const OFF_ARGS: u32 = 16; // offsetof(seccomp_data, args)
+
+/// Architectures the filter has syscall tables for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Arch {
+    #[serde(rename = "x86_64")]
+    X86_64,
+    #[serde(rename = "aarch64")]
+    Aarch64,
+    /// i386, including 32-bit processes on x86_64 hosts
+    #[serde(rename = "x86")]
+    X86,
+    /// 32-bit ARM, including compat processes on aarch64 hosts
+    #[serde(rename = "arm")]
+    Arm,
+}
+
+impl Arch {
+    pub const ALL: [Arch; 4] = [Arch::X86_64, Arch::Aarch64, Arch::X86, Arch::Arm];
+    pub const NATIVE: [Arch; 2] = [Arch::X86_64, Arch::Aarch64];
+    const COMPAT: [Arch; 2] = [Arch::X86, Arch::Arm];
+
+    /// The architecture with the given `AUDIT_ARCH_*` value, as reported in
+    /// `seccomp_data`.
+    pub fn from_audit_arch(audit_arch: u32) -> Option<Arch> {
+        Arch::ALL
+            .into_iter()
+            .find(|arch| arch.audit_arch() == audit_arch)
+    }
+
+    fn audit_arch(self) -> u32 {
+        match self {
+            Arch::X86_64 => AUDIT_ARCH_X86_64,
+            Arch::Aarch64 => AUDIT_ARCH_AARCH64,
+            Arch::X86 => AUDIT_ARCH_I386,
+            Arch::Arm => AUDIT_ARCH_ARM,
+        }
+    }
+
+    pub fn syscall_nr(self, name: &str) -> Option<u32> {
+        match self {
+            Arch::X86_64 => syscalls::lookup(syscalls::X86_64, name),
+            Arch::Aarch64 => syscalls::lookup(syscalls::AARCH64, name),
+            Arch::X86 => syscalls::lookup(syscalls::X86, name),
+            Arch::Arm => syscalls::lookup(syscalls::ARM, name),
+        }
+    }
+}
+
+/// Whether `name` is a syscall on any supported architecture.
+pub fn is_known_syscall(name: &str) -> bool {
+    Arch::ALL.iter().any(|arch| arch.syscall_nr(name).is_some())
+}
+
+/// What the filter does with a matching syscall.
 ///
-/// The filter checks the architecture is x86_64 or aarch64, then blocks
-/// `socket()` calls where the first argument (domain) is `AF_UNIX`. All other
-/// syscalls and socket domains pass through. Operations on inherited/pre-existing
-/// unix socket FDs are not affected.
-pub fn block_unix_sockets() -> io::Result<()> {
-    const AUDIT_ARCH_X86_64: u32 = 0xC000_003E;
-    const AUDIT_ARCH_AARCH64: u32 = 0xC000_00B7;
-    const NR_SOCKET_X86_64: u32 = 41; // __NR_socket on x86_64
-    const NR_SOCKET_AARCH64: u32 = 198; // __NR_socket on aarch64
-    const AF_UNIX: u32 = 1;
-    const SECCOMP_RET_ALLOW: u32 = 0x7FFF_0000;
-    const SECCOMP_RET_ERRNO: u32 = 0x0005_0000;
-    const EACCES: u32 = 13;
-
-    // BPF instruction constants (pre-combined)
-    const BPF_LD_W_ABS: u16 = 0x20; // BPF_LD | BPF_W | BPF_ABS
-    const BPF_JMP_JEQ_K: u16 = 0x15; // BPF_JMP | BPF_JEQ | BPF_K
-    const BPF_RET_K: u16 = 0x06; // BPF_RET | BPF_K
-
-    // seccomp_data field offsets
-    const OFF_NR: u32 = 0; // offsetof(seccomp_data, nr)
-    const OFF_ARCH: u32 = 4; // offsetof(seccomp_data, arch)
-    const OFF_ARGS0: u32 = 16; // offsetof(seccomp_data, args[0])
-
-    #[rustfmt::skip]
-    let mut filter: [libc::sock_filter; 11] = [
-        // [0] Load arch
-        libc::sock_filter { code: BPF_LD_W_ABS, jt: 0, jf: 0, k: OFF_ARCH },
-        // [1] If arch == x86_64, jump to x86_64 handler at [3]
-        libc::sock_filter { code: BPF_JMP_JEQ_K, jt: 1, jf: 0, k: AUDIT_ARCH_X86_64 },
-        // [2] If arch == aarch64, jump to aarch64 handler at [5]; else ALLOW at [10]
-        libc::sock_filter { code: BPF_JMP_JEQ_K, jt: 2, jf: 7, k: AUDIT_ARCH_AARCH64 },
-        // --- x86_64 path ---
-        // [3] Load syscall number
-        libc::sock_filter { code: BPF_LD_W_ABS, jt: 0, jf: 0, k: OFF_NR },
-        // [4] If nr == __NR_socket (x86_64), jump to domain check at [7]; else ALLOW at [10]
-        libc::sock_filter { code: BPF_JMP_JEQ_K, jt: 2, jf: 5, k: NR_SOCKET_X86_64 },
-        // --- aarch64 path ---
-        // [5] Load syscall number
-        libc::sock_filter { code: BPF_LD_W_ABS, jt: 0, jf: 0, k: OFF_NR },
-        // [6] If nr == __NR_socket (aarch64), continue to domain check; else ALLOW at [10]
-        libc::sock_filter { code: BPF_JMP_JEQ_K, jt: 0, jf: 3, k: NR_SOCKET_AARCH64 },
-        // --- common domain check ---
-        // [7] Load socket domain (args[0])
-        libc::sock_filter { code: BPF_LD_W_ABS, jt: 0, jf: 0, k: OFF_ARGS0 },
-        // [8] If domain == AF_UNIX, block; else ALLOW at [10]
-        libc::sock_filter { code: BPF_JMP_JEQ_K, jt: 0, jf: 1, k: AF_UNIX },
-        // [9] Return SECCOMP_RET_ERRNO | EACCES
-        libc::sock_filter { code: BPF_RET_K, jt: 0, jf: 0, k: SECCOMP_RET_ERRNO | EACCES },
-        // [10] Return SECCOMP_RET_ALLOW
-        libc::sock_filter { code: BPF_RET_K, jt: 0, jf: 0, k: SECCOMP_RET_ALLOW },
-    ];
-
-    let prog = libc::sock_fprog {
-        len: filter.len() as u16,
-        filter: filter.as_mut_ptr(),
+/// In config files: `"allow"`, `"kill"`, `"log"` or `{"errno": 1}`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Allow,
+    /// Fail the syscall with this errno
+    Errno(u16),
+    /// Kill the whole process
+    Kill,
+    /// Allow, but record the call in the audit log
+    Log,
+    /// Suspend the call until the broker in the parent process answers it.
+    /// Only used by the built-in rules.
+    #[serde(skip)]
+    Notify,
+}
+
+impl Action {
+    fn ret_value(self) -> u32 {
+        match self {
+            Action::Allow => SECCOMP_RET_ALLOW,
+            Action::Errno(errno) => SECCOMP_RET_ERRNO | u32::from(errno),
+            Action::Kill => SECCOMP_RET_KILL_PROCESS,
+            Action::Log => SECCOMP_RET_LOG,
+            Action::Notify => SECCOMP_RET_USER_NOTIF,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// A comparison on a syscall argument: `(args[index] & mask) <op> value`,
+/// unsigned and 64 bits wide.
+///
+/// Arguments the kernel treats as `int` should use `mask: 0xffffffff`, as the
+/// upper half of the register is ignored by the kernel but not by the filter.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ArgCmp {
+    pub index: u8,
+    pub op: CmpOp,
+    pub value: u64,
+    #[serde(default = "full_mask")]
+    pub mask: u64,
+}
+
+fn full_mask() -> u64 {
+    u64::MAX
+}
+
+/// A single syscall rule. The first rule matching a call decides its fate.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Rule {
+    pub syscall: String,
+    /// Architectures the rule applies to; all supported ones when empty
+    #[serde(default)]
+    pub arches: Vec<Arch>,
+    /// Comparisons that must all hold for the rule to match
+    #[serde(default)]
+    pub args: Vec<ArgCmp>,
+    pub action: Action,
+}
+
+/// An ordered rule list plus the action for syscalls no rule matches.
+pub struct Policy {
+    pub rules: Vec<Rule>,
+    pub default_action: Action,
+    /// Architectures whose syscalls go through the rules
+    pub arches: Vec<Arch>,
+    /// Action for syscalls from any other architecture or from the x32 ABI,
+    /// which would otherwise reach the kernel under numbers no rule checks
+    pub foreign_arch_action: Action,
+}
+
+/// Jump target in a program under construction.
+#[derive(Clone, Copy)]
+struct Label(usize);
+
+enum Insn {
+    Plain(libc::sock_filter),
+    /// Unconditional jump, resolved once all labels are placed
+    Jump(Label),
+}
+
+/// Builds a BPF program with symbolic jump targets.
+///
+/// Conditional jumps only ever skip the instruction that follows them, which
+/// is an unconditional `BPF_JA` to the real target. Its 32-bit offset removes
+/// the 255-instruction reach of conditional jumps from the picture.
+#[derive(Default)]
+struct Assembler {
+    insns: Vec<Insn>,
+    labels: Vec<Option<usize>>,
+}
+
+impl Assembler {
+    fn stmt(&mut self, code: u16, k: u32) {
+        self.insns.push(Insn::Plain(libc::sock_filter {
+            code,
+            jt: 0,
+            jf: 0,
+            k,
+        }));
+    }
+
+    fn load(&mut self, offset: u32) {
+        self.stmt(BPF_LD_W_ABS, offset);
+    }
+
+    fn ret(&mut self, action: Action) {
+        self.stmt(BPF_RET_K, action.ret_value());
+    }
+
+    fn new_label(&mut self) -> Label {
+        self.labels.push(None);
+        Label(self.labels.len() - 1)
+    }
+
+    fn place(&mut self, label: Label) {
+        self.labels[label.0] = Some(self.insns.len());
+    }
+
+    /// Return `action` if the condition `code`/`k` holds on the accumulator.
+    fn ret_if(&mut self, code: u16, k: u32, action: Action) {
+        self.insns.push(Insn::Plain(libc::sock_filter {
+            code,
+            jt: 0,
+            jf: 1,
+            k,
+        }));
+        self.ret(action);
+    }
+
+    /// Jump to `target` if the condition `code`/`k` holds on the accumulator.
+    fn jump_if(&mut self, code: u16, k: u32, target: Label) {
+        self.insns.push(Insn::Plain(libc::sock_filter {
+            code,
+            jt: 0,
+            jf: 1,
+            k,
+        }));
+        self.insns.push(Insn::Jump(target));
+    }
+
+    /// Jump to `target` unless the condition `code`/`k` holds on the accumulator.
+    fn jump_unless(&mut self, code: u16, k: u32, target: Label) {
+        self.insns.push(Insn::Plain(libc::sock_filter {
+            code,
+            jt: 1,
+            jf: 0,
+            k,
+        }));
+        self.insns.push(Insn::Jump(target));
+    }
+
+    fn finish(self) -> Result<Vec<libc::sock_filter>, String> {
+        if self.insns.len() > BPF_MAXINSNS {
+            return Err(format!(
+                "seccomp policy compiles to {} instructions, more than the kernel limit of {BPF_MAXINSNS}",
+                self.insns.len()
+            ));
+        }
+        Ok(self
+            .insns
+            .iter()
+            .enumerate()
+            .map(|(pc, insn)| match insn {
+                Insn::Plain(filter) => *filter,
+                Insn::Jump(label) => {
+                    let target = self.labels[label.0].expect("jump to unplaced label");
+                    libc::sock_filter {
+                        code: BPF_JMP_JA,
+                        jt: 0,
+                        jf: 0,
+                        k: target
+                            .checked_sub(pc + 1)
+                            .expect("BPF jumps can only go forward")
+                            as u32,
+                    }
+                }
+            })
+            .collect())
+    }
+}
+
+/// Emit code that falls through if `cmp` holds and jumps to `fail` otherwise.
+///
+/// BPF only handles 32-bit words, so the upper halves are compared first and
+/// the lower halves decide only when the upper halves are equal.
+fn emit_arg_cmp(asm: &mut Assembler, cmp: &ArgCmp, fail: Label) {
+    // Arguments are little-endian on every supported architecture.
+    let offset = OFF_ARGS + 8 * u32::from(cmp.index);
+    let (mask_hi, mask_lo) = ((cmp.mask >> 32) as u32, cmp.mask as u32);
+    let (value_hi, value_lo) = ((cmp.value >> 32) as u32, cmp.value as u32);
+
+    let load = |asm: &mut Assembler, offset: u32, mask: u32| {
+        asm.load(offset);
+        if mask != u32::MAX {
+            asm.stmt(BPF_ALU_AND_K, mask);
+        }
     };
 
-    // PR_SET_NO_NEW_PRIVS is required before installing a seccomp filter
-    // as an unprivileged user.
-    let ret = unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) };
-    if ret != 0 {
-        return Err(io::Error::last_os_error());
+    let ok = asm.new_label();
+    load(asm, offset + 4, mask_hi);
+    match cmp.op {
+        CmpOp::Eq => {
+            asm.jump_unless(BPF_JMP_JEQ_K, value_hi, fail);
+            load(asm, offset, mask_lo);
+            asm.jump_unless(BPF_JMP_JEQ_K, value_lo, fail);
+        }
+        CmpOp::Ne => {
+            asm.jump_unless(BPF_JMP_JEQ_K, value_hi, ok);
+            load(asm, offset, mask_lo);
+            asm.jump_if(BPF_JMP_JEQ_K, value_lo, fail);
+        }
+        CmpOp::Gt | CmpOp::Ge => {
+            asm.jump_if(BPF_JMP_JGT_K, value_hi, ok);
+            asm.jump_unless(BPF_JMP_JEQ_K, value_hi, fail);
+            load(asm, offset, mask_lo);
+            let code = if cmp.op == CmpOp::Gt {
+                BPF_JMP_JGT_K
+            } else {
+                BPF_JMP_JGE_K
+            };
+            asm.jump_unless(code, value_lo, fail);
+        }
+        CmpOp::Lt | CmpOp::Le => {
+            asm.jump_if(BPF_JMP_JGT_K, value_hi, fail);
+            asm.jump_unless(BPF_JMP_JEQ_K, value_hi, ok);
+            load(asm, offset, mask_lo);
+            let code = if cmp.op == CmpOp::Lt {
+                BPF_JMP_JGE_K
+            } else {
+                BPF_JMP_JGT_K
+            };
+            asm.jump_if(code, value_lo, fail);
+        }
+    }
+    asm.place(ok);
+}
+
+impl Policy {
+    /// Compile the policy to a BPF program.
+    ///
+    /// The program dispatches on the architecture, then tries each rule for
+    /// that architecture in order. Calls from architectures outside
+    /// `self.arches` get `foreign_arch_action`.
+    pub fn compile(&self) -> Result<Vec<libc::sock_filter>, String> {
+        for rule in &self.rules {
+            let arches = if rule.arches.is_empty() {
+                &Arch::ALL[..]
+            } else {
+                &rule.arches[..]
+            };
+            if arches
+                .iter()
+                .all(|arch| arch.syscall_nr(&rule.syscall).is_none())
+            {
+                return Err(format!("unknown syscall '{}'", rule.syscall));
+            }
+            if let Some(cmp) = rule.args.iter().find(|cmp| cmp.index > 5) {
+                return Err(format!(
+                    "syscall '{}' has no argument {}",
+                    rule.syscall, cmp.index
+                ));
+            }
+        }
+
+        let mut asm = Assembler::default();
+        let arch_labels: Vec<Label> = self.arches.iter().map(|_| asm.new_label()).collect();
+
+        asm.load(OFF_ARCH);
+        for (arch, &label) in self.arches.iter().zip(&arch_labels) {
+            asm.jump_if(BPF_JMP_JEQ_K, arch.audit_arch(), label);
+        }
+        asm.ret(self.foreign_arch_action);
+
+        for (&arch, &label) in self.arches.iter().zip(&arch_labels) {
+            asm.place(label);
+            asm.load(OFF_NR);
+            // Before the x32 check, which the skip number would also match.
+            asm.ret_if(BPF_JMP_JEQ_K, SKIPPED_SYSCALL, Action::Allow);
+            if arch == Arch::X86_64 {
+                asm.ret_if(BPF_JMP_JGE_K, X32_SYSCALL_BIT, self.foreign_arch_action);
+            }
+            // Whether the accumulator still holds the syscall number
+            let mut nr_loaded = true;
+            for rule in &self.rules {
+                if !rule.arches.is_empty() && !rule.arches.contains(&arch) {
+                    continue;
+                }
+                let Some(nr) = arch.syscall_nr(&rule.syscall) else {
+                    continue;
+                };
+                if !nr_loaded {
+                    asm.load(OFF_NR);
+                }
+                if rule.args.is_empty() {
+                    asm.ret_if(BPF_JMP_JEQ_K, nr, rule.action);
+                    nr_loaded = true;
+                    continue;
+                }
+                let next = asm.new_label();
+                asm.jump_unless(BPF_JMP_JEQ_K, nr, next);
+                for cmp in &rule.args {
+                    emit_arg_cmp(&mut asm, cmp, next);
+                }
+                asm.ret(rule.action);
+                asm.place(next);
+                nr_loaded = false;
+            }
+            asm.ret(self.default_action);
+        }
+
+        asm.finish()
+    }
+
+    /// Compile the policy and install it on the calling thread.
+    pub fn install(&self) -> io::Result<()> {
+        let mut filter = self.prepare_install()?;
+        let prog = libc::sock_fprog {
+            len: filter.len() as u16,
+            filter: filter.as_mut_ptr(),
+        };
+
+        let ret = unsafe {
+            libc::prctl(
+                libc::PR_SET_SECCOMP,
+                libc::SECCOMP_MODE_FILTER,
+                &prog as *const libc::sock_fprog,
+            )
+        };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    /// Like `install`, but also return the listener fd that receives the
+    /// calls the policy answers with `Action::Notify`.
+    pub fn install_with_listener(&self) -> io::Result<OwnedFd> {
+        let mut filter = self.prepare_install()?;
+        let prog = libc::sock_fprog {
+            len: filter.len() as u16,
+            filter: filter.as_mut_ptr(),
+        };
+
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_seccomp,
+                libc::SECCOMP_SET_MODE_FILTER,
+                libc::SECCOMP_FILTER_FLAG_NEW_LISTENER,
+                &prog as *const libc::sock_fprog,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(unsafe { OwnedFd::from_raw_fd(fd as i32) })
+    }
+
+    /// Compile the policy and set the no_new_privs bit filters need.
+    fn prepare_install(&self) -> io::Result<Vec<libc::sock_filter>> {
+        let filter = self
+            .compile()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+        // PR_SET_NO_NEW_PRIVS is required before installing a seccomp filter
+        // as an unprivileged user.
+        let ret = unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(filter)
+    }
+}
+
+/// Block `socket(AF_UNIX, ...)` with EACCES. Operations on inherited or
+/// pre-existing unix socket FDs are not affected.
+///
+/// With `broker`, `socket(AF_UNIX, ...)` and every `connect` go to the
+/// broker in the parent instead (see `seccomp_notify`), which decides per
+/// socket path.
+///
+/// On i386, `socketcall(SYS_SOCKET, args)` creates sockets too. Its domain
+/// sits in user memory the filter cannot read, so it is blocked outright;
+/// libc has used the direct `socket` syscall there since Linux 4.3.
+fn unix_socket_rules(broker: bool) -> Vec<Rule> {
+    // `domain` and the socketcall `call` are ints, so only their low 32 bits count.
+    let int_eq = |index, value| ArgCmp {
+        index,
+        op: CmpOp::Eq,
+        value,
+        mask: u32::MAX.into(),
+    };
+    let unix_domain = vec![int_eq(0, libc::AF_UNIX as u64)];
+    let socketcall = deny_rule("socketcall", libc::EACCES, vec![int_eq(0, SYS_SOCKET)]);
+    if !broker {
+        return vec![deny_rule("socket", libc::EACCES, unix_domain), socketcall];
     }
+    let notify = |syscall: &str, args| Rule {
+        syscall: syscall.to_string(),
+        arches: Vec::new(),
+        args,
+        action: Action::Notify,
+    };
+    vec![
+        notify("socket", unix_domain),
+        socketcall,
+        // The address family sits in user memory, so the broker checks it.
+        notify("connect", Vec::new()),
+    ]
+}
+
+/// Deny `TIOCSTI`, which pushes bytes into a terminal's input queue, and
+/// `TIOCLINUX`, whose console selection can paste text. The sandbox shares
+/// the user's terminal, so injected input would run in the host shell once
+/// ajail exits.
+fn terminal_rules() -> Vec<Rule> {
+    [TIOCSTI, TIOCLINUX]
+        .into_iter()
+        .map(|request| {
+            deny_rule(
+                "ioctl",
+                libc::EPERM,
+                // The request is an unsigned int in the kernel.
+                vec![ArgCmp {
+                    index: 1,
+                    op: CmpOp::Eq,
+                    value: request,
+                    mask: u32::MAX.into(),
+                }],
+            )
+        })
+        .collect()
+}
+
+/// Deny io_uring with ENOSYS, so programs fall back to regular syscalls.
+/// Its operations (e.g. `IORING_OP_SOCKET`) never pass through the filter,
+/// so allowing it lets the sandbox create Unix sockets despite the rules above.
+fn io_uring_rules() -> Vec<Rule> {
+    ["io_uring_setup", "io_uring_enter", "io_uring_register"]
+        .into_iter()
+        .map(|syscall| deny_rule(syscall, libc::ENOSYS, Vec::new()))
+        .collect()
+}
 
-    let ret = unsafe {
-        libc::prctl(
-            libc::PR_SET_SECCOMP,
-            libc::SECCOMP_MODE_FILTER,
-            &prog as *const libc::sock_fprog,
+/// The hardening profile: `HARDENING_DENY` plus ptrace attach, so the
+/// sandbox cannot trace processes it did not start as tracees. Debuggers
+/// that launch their target with `PTRACE_TRACEME` (gdb) keep working.
+fn hardening_rules() -> Vec<Rule> {
+    let ptrace_request = |request| {
+        deny_rule(
+            "ptrace",
+            libc::EPERM,
+            vec![ArgCmp {
+                index: 0,
+                op: CmpOp::Eq,
+                value: request,
+                mask: full_mask(),
+            }],
         )
     };
-    if ret != 0 {
-        return Err(io::Error::last_os_error());
+    HARDENING_DENY
+        .iter()
+        .map(|syscall| deny_rule(syscall, libc::EPERM, Vec::new()))
+        .chain([ptrace_request(PTRACE_ATTACH), ptrace_request(PTRACE_SEIZE)])
+        .collect()
+}
+
+fn deny_rule(syscall: &str, errno: i32, args: Vec<ArgCmp>) -> Rule {
+    Rule {
+        syscall: syscall.to_string(),
+        arches: Vec::new(),
+        args,
+        action: Action::Errno(errno as u16),
+    }
+}
+
+/// Whether Unix socket connections go through the notify broker rather
+/// than failing outright. Allow patterns turn the broker on by themselves.
+pub fn brokers_unix_sockets(options: &Options) -> bool {
+    (options.seccomp_notify || !options.unix_socket_allow.is_empty()) && !options.allow_unix_sockets
+}
+
+/// The policy for the sandboxed command: rules from the config first, so
+/// they can override the built-in ones, then the built-in rules.
+pub fn sandbox_policy(options: &Options) -> Policy {
+    let mut rules = options.seccomp_rules.clone();
+    rules.extend(terminal_rules());
+    if !options.allow_unix_sockets {
+        rules.extend(unix_socket_rules(brokers_unix_sockets(options)));
+    }
+    if !options.allow_io_uring {
+        rules.extend(io_uring_rules());
+    }
+    if !options.disable_seccomp_hardening {
+        rules.extend(hardening_rules());
+    }
+    let mut arches = Arch::NATIVE.to_vec();
+    if options.allow_compat_syscalls {
+        arches.extend(Arch::COMPAT);
     }
+    Policy {
+        rules,
+        default_action: Action::Allow,
+        arches,
+        foreign_arch_action: Action::Kill,
+    }
+}
 
-    Ok(())
+/// The policy for ajail's init processes in the sandbox (see `init`): only
+/// `INIT_ALLOW`, so a process that gains control of one cannot do more.
+pub fn init_policy() -> Policy {
+    Policy {
+        rules: INIT_ALLOW
+            .iter()
+            .map(|syscall| Rule {
+                syscall: syscall.to_string(),
+                arches: Vec::new(),
+                args: Vec::new(),
+                action: Action::Allow,
+            })
+            .collect(),
+        default_action: Action::Errno(libc::EPERM as u16),
+        arches: Arch::NATIVE.to_vec(),
+        foreign_arch_action: Action::Kill,
+    }
 }
@@ -0,0 +1,525 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::{CString, OsStr};
+use std::fs::{self, File};
+use std::io::{self, BufRead, Write};
+use std::os::fd::{AsFd, AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileExt, MetadataExt, OpenOptionsExt};
+use std::os::unix::net::UnixStream;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use nix::sys::socket::{AddressFamily, SockFlag, SockType, UnixAddr, connect, socket};
+
+use crate::glob;
+use crate::pty;
+use crate::seccomp::Arch;
+use crate::util::{receive_fd, send_fd};
+
+// ioctls on the listener fd (linux/seccomp.h)
+const SECCOMP_IOCTL_NOTIF_RECV: libc::c_ulong = 0xC050_2100; // _IOWR('!', 0, struct seccomp_notif)
+const SECCOMP_IOCTL_NOTIF_SEND: libc::c_ulong = 0xC018_2101; // _IOWR('!', 1, struct seccomp_notif_resp)
+const SECCOMP_IOCTL_NOTIF_ID_VALID: libc::c_ulong = 0x4008_2102; // _IOW('!', 2, __u64)
+const SECCOMP_IOCTL_NOTIF_ADDFD: libc::c_ulong = 0x4018_2103; // _IOW('!', 3, struct seccomp_notif_addfd)
+
+/// `sizeof(struct sockaddr_un)`, the longest address `connect` accepts
+const SOCKADDR_UN_LEN: usize = 110;
+
+/// Running broker. Records its decisions for the exit report.
+pub struct NotifyBroker {
+    decisions: Arc<Mutex<BTreeMap<String, Tally>>>,
+}
+
+#[derive(Default)]
+struct Tally {
+    allowed: usize,
+    denied: usize,
+}
+
+/// How to answer a suspended syscall.
+enum Reply {
+    /// Let the kernel run the call as issued
+    Continue,
+    /// Fail the call with this errno
+    Error(i32),
+    /// Return this value without running the call
+    Value(i64),
+    /// Already answered, along with an injected fd
+    Sent,
+}
+
+/// State of the thread serving the listener.
+///
+/// The sandbox never holds an unconnected Unix socket of its own:
+/// `socket(AF_UNIX, ...)` is answered with a placeholder memfd, and a
+/// `connect` on a placeholder is carried out by the broker, which then swaps
+/// the connected socket in. The path checked is the path connected to, so
+/// the sandbox cannot change it in between, and it is resolved without
+/// following symlinks so it cannot point the host somewhere else.
+///
+/// Until it is connected a placeholder is not a socket: `setsockopt`,
+/// `bind` and the like on it fail with `ENOTSOCK`.
+struct Broker {
+    listener: OwnedFd,
+    allow: Vec<String>,
+    /// Paths allowed for the rest of the session at the prompt
+    always: HashSet<String>,
+    /// Socket type requested for each placeholder, by device and inode
+    placeholders: HashMap<(u64, u64), i32>,
+    decisions: Arc<Mutex<BTreeMap<String, Tally>>>,
+}
+
+/// Hand the listener of the filter just installed to the parent over `channel`.
+pub fn send_listener(channel: &UnixStream, listener: OwnedFd) -> io::Result<()> {
+    send_fd(channel, listener.as_fd())
+}
+
+/// Receive the listener sent by `send_listener`. Fails with `UnexpectedEof`
+/// if the sandbox exited before sending it.
+pub fn receive_listener(channel: &UnixStream) -> io::Result<OwnedFd> {
+    receive_fd(channel, "seccomp listener")
+}
+
+impl NotifyBroker {
+    /// Answer the sandbox's brokered syscalls on `listener`. Connections to
+    /// socket paths matching `allow` go through; for others the user is
+    /// asked on /dev/tty, and without a terminal they are denied.
+    pub fn spawn(listener: OwnedFd, allow: Vec<String>) -> Self {
+        let decisions = Arc::new(Mutex::new(BTreeMap::new()));
+        let mut broker = Broker {
+            listener,
+            allow,
+            always: HashSet::new(),
+            placeholders: HashMap::new(),
+            decisions: Arc::clone(&decisions),
+        };
+        thread::spawn(move || broker.serve());
+        NotifyBroker { decisions }
+    }
+
+    /// Print the Unix socket connections the broker decided on.
+    pub fn report(&self) {
+        let decisions = self.decisions.lock().unwrap_or_else(|e| e.into_inner());
+        if decisions.is_empty() {
+            return;
+        }
+        eprintln!("ajail: Unix socket connections brokered during the session:");
+        for (path, tally) in decisions.iter() {
+            let mut counts = Vec::new();
+            if tally.allowed > 0 {
+                counts.push(format!("allowed {}x", tally.allowed));
+            }
+            if tally.denied > 0 {
+                counts.push(format!("denied {}x", tally.denied));
+            }
+            eprintln!("  {path} ({})", counts.join(", "));
+        }
+    }
+}
+
+impl Broker {
+    fn serve(&mut self) {
+        loop {
+            let mut pollfd = libc::pollfd {
+                fd: self.listener.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            if unsafe { libc::poll(&mut pollfd, 1, -1) } < 0 {
+                if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return;
+            }
+            // Every process using the filter has exited.
+            if pollfd.revents & libc::POLLIN == 0 {
+                return;
+            }
+
+            let mut notif: libc::seccomp_notif = unsafe { std::mem::zeroed() };
+            if let Err(e) = self.ioctl(SECCOMP_IOCTL_NOTIF_RECV, &mut notif) {
+                // ENOENT: the caller died before we got to its call.
+                match e.raw_os_error() {
+                    Some(libc::ENOENT | libc::EINTR) => continue,
+                    _ => return,
+                }
+            }
+
+            let reply = self.handle(&notif);
+            let mut resp = libc::seccomp_notif_resp {
+                id: notif.id,
+                val: 0,
+                error: 0,
+                flags: 0,
+            };
+            match reply {
+                Reply::Continue => resp.flags = libc::SECCOMP_USER_NOTIF_FLAG_CONTINUE as u32,
+                Reply::Error(errno) => resp.error = -errno,
+                Reply::Value(val) => resp.val = val,
+                Reply::Sent => continue,
+            }
+            // Fails only if the caller is gone, which needs no answer.
+            let _ = self.ioctl(SECCOMP_IOCTL_NOTIF_SEND, &mut resp);
+        }
+    }
+
+    fn handle(&mut self, notif: &libc::seccomp_notif) -> Reply {
+        let arch = Arch::from_audit_arch(notif.data.arch);
+        let is = |name| arch.and_then(|arch| arch.syscall_nr(name)) == Some(notif.data.nr as u32);
+        if is("socket") {
+            self.create_placeholder(notif)
+        } else if is("connect") {
+            self.connect(notif)
+        } else {
+            Reply::Continue
+        }
+    }
+
+    /// Answer `socket(AF_UNIX, type, 0)` with a fresh placeholder fd.
+    fn create_placeholder(&mut self, notif: &libc::seccomp_notif) -> Reply {
+        let ty = notif.data.args[1] as i32;
+        let fd = unsafe { libc::memfd_create(c"ajail-unix-socket".as_ptr(), libc::MFD_CLOEXEC) };
+        if fd < 0 {
+            return Reply::Error(libc::ENOMEM);
+        }
+        let placeholder = unsafe { File::from_raw_fd(fd) };
+        if ty & libc::SOCK_NONBLOCK != 0 {
+            unsafe { libc::fcntl(fd, libc::F_SETFL, libc::O_NONBLOCK) };
+        }
+        let Ok(meta) = placeholder.metadata() else {
+            return Reply::Error(libc::ENOMEM);
+        };
+        self.placeholders.insert(
+            (meta.dev(), meta.ino()),
+            ty & !(libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC),
+        );
+
+        let newfd_flags = if ty & libc::SOCK_CLOEXEC != 0 {
+            libc::O_CLOEXEC as u32
+        } else {
+            0
+        };
+        let mut addfd = libc::seccomp_notif_addfd {
+            id: notif.id,
+            flags: libc::SECCOMP_ADDFD_FLAG_SEND as u32,
+            srcfd: fd as u32,
+            newfd: 0,
+            newfd_flags,
+        };
+        match self.ioctl(SECCOMP_IOCTL_NOTIF_ADDFD, &mut addfd) {
+            Ok(_) => Reply::Sent,
+            Err(e) => Reply::Error(e.raw_os_error().unwrap_or(libc::EIO)),
+        }
+    }
+
+    /// Handle `connect(fd, addr, len)`. Calls on fds other than placeholders
+    /// run as issued.
+    fn connect(&mut self, notif: &libc::seccomp_notif) -> Reply {
+        let pid = notif.pid;
+        let fd = notif.data.args[0] as RawFd;
+        let Some((placeholder, ty)) = self.placeholder(pid, fd) else {
+            return Reply::Continue;
+        };
+
+        let addr = match read_sockaddr(pid, notif.data.args[1], notif.data.args[2] as usize) {
+            Ok(addr) => addr,
+            Err(errno) => return Reply::Error(errno),
+        };
+        // The read may have come from a recycled pid.
+        if self
+            .ioctl(SECCOMP_IOCTL_NOTIF_ID_VALID, &mut { notif.id })
+            .is_err()
+        {
+            return Reply::Sent;
+        }
+        let target = match SocketTarget::parse(&addr) {
+            Ok(target) => target,
+            Err(errno) => return Reply::Error(errno),
+        };
+
+        let name = target.to_string();
+        let allowed = self.decide(&name, pid);
+        {
+            let mut decisions = self.decisions.lock().unwrap_or_else(|e| e.into_inner());
+            let tally = decisions.entry(name).or_default();
+            if allowed {
+                tally.allowed += 1;
+            } else {
+                tally.denied += 1;
+            }
+        }
+        if !allowed {
+            return Reply::Error(libc::EACCES);
+        }
+
+        let socket = match target.connect(pid, ty) {
+            Ok(socket) => socket,
+            Err(errno) => return Reply::Error(errno),
+        };
+        let nonblocking =
+            unsafe { libc::fcntl(placeholder.as_raw_fd(), libc::F_GETFL) } & libc::O_NONBLOCK != 0;
+        if nonblocking {
+            unsafe { libc::fcntl(socket.as_raw_fd(), libc::F_SETFL, libc::O_NONBLOCK) };
+        }
+        let newfd_flags = if fd_cloexec(pid, fd) {
+            libc::O_CLOEXEC as u32
+        } else {
+            0
+        };
+        let mut addfd = libc::seccomp_notif_addfd {
+            id: notif.id,
+            flags: libc::SECCOMP_ADDFD_FLAG_SETFD as u32,
+            srcfd: socket.as_raw_fd() as u32,
+            newfd: fd as u32,
+            newfd_flags,
+        };
+        match self.ioctl(SECCOMP_IOCTL_NOTIF_ADDFD, &mut addfd) {
+            Ok(_) => Reply::Value(0),
+            Err(e) => Reply::Error(e.raw_os_error().unwrap_or(libc::EIO)),
+        }
+    }
+
+    /// A copy of the caller's `fd` and the requested socket type, if it is
+    /// one of our placeholders.
+    fn placeholder(&self, pid: u32, fd: RawFd) -> Option<(OwnedFd, i32)> {
+        let pidfd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) };
+        if pidfd < 0 {
+            return None;
+        }
+        let pidfd = unsafe { OwnedFd::from_raw_fd(pidfd as RawFd) };
+        let copy = unsafe { libc::syscall(libc::SYS_pidfd_getfd, pidfd.as_raw_fd(), fd, 0) };
+        if copy < 0 {
+            return None;
+        }
+        let copy = File::from(unsafe { OwnedFd::from_raw_fd(copy as RawFd) });
+        let meta = copy.metadata().ok()?;
+        let ty = *self.placeholders.get(&(meta.dev(), meta.ino()))?;
+        Some((copy.into(), ty))
+    }
+
+    /// Whether to let the sandbox connect to `name`: by pattern, by an
+    /// earlier "always" answer, or by asking on /dev/tty.
+    fn decide(&mut self, name: &str, pid: u32) -> bool {
+        if self.always.contains(name) || self.allow.iter().any(|p| glob::matches(p, name)) {
+            return true;
+        }
+        match pty::with_host_terminal(|| prompt_connect(name, pid)) {
+            Answer::Once => true,
+            Answer::Always => {
+                self.always.insert(name.to_string());
+                true
+            }
+            Answer::Deny => false,
+        }
+    }
+
+    fn ioctl<T>(&self, request: libc::c_ulong, arg: &mut T) -> io::Result<i32> {
+        let ret = unsafe { libc::ioctl(self.listener.as_raw_fd(), request, arg as *mut T) };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(ret)
+    }
+}
+
+/// Read the `sockaddr` a `connect` call points at from the caller's memory.
+fn read_sockaddr(pid: u32, ptr: u64, len: usize) -> Result<Vec<u8>, i32> {
+    if !(2..=SOCKADDR_UN_LEN).contains(&len) {
+        return Err(libc::EINVAL);
+    }
+    let mem = File::open(format!("/proc/{pid}/mem")).map_err(|_| libc::EFAULT)?;
+    let mut addr = vec![0u8; len];
+    mem.read_exact_at(&mut addr, ptr)
+        .map_err(|_| libc::EFAULT)?;
+    Ok(addr)
+}
+
+/// Whether `fd` of process `pid` is close-on-exec, from its fdinfo.
+fn fd_cloexec(pid: u32, fd: RawFd) -> bool {
+    let fdinfo = fs::read_to_string(format!("/proc/{pid}/fdinfo/{fd}")).unwrap_or_default();
+    fdinfo
+        .lines()
+        .find_map(|line| line.strip_prefix("flags:"))
+        .and_then(|flags| u32::from_str_radix(flags.trim(), 8).ok())
+        .is_some_and(|flags| flags & libc::O_CLOEXEC as u32 != 0)
+}
+
+/// A Unix socket address as given to `connect`.
+enum SocketTarget {
+    /// A filesystem path, absolute or relative to the caller's cwd
+    Path(PathBuf),
+    /// A name in the abstract namespace
+    Abstract(Vec<u8>),
+}
+
+impl SocketTarget {
+    fn parse(addr: &[u8]) -> Result<Self, i32> {
+        let family = u16::from_ne_bytes([addr[0], addr[1]]);
+        if family != libc::AF_UNIX as u16 {
+            return Err(libc::EAFNOSUPPORT);
+        }
+        match &addr[2..] {
+            [] => Err(libc::EINVAL),
+            [0, name @ ..] => Ok(SocketTarget::Abstract(name.to_vec())),
+            path => {
+                let end = path.iter().position(|&b| b == 0).unwrap_or(path.len());
+                Ok(SocketTarget::Path(normalize(Path::new(OsStr::from_bytes(
+                    &path[..end],
+                )))))
+            }
+        }
+    }
+
+    /// Connect a new socket of type `ty` to the target as seen by process
+    /// `pid`. Paths are looked up in the sandbox's mount namespace and must
+    /// name a socket; abstract names in the parent's network namespace.
+    fn connect(&self, pid: u32, ty: i32) -> Result<OwnedFd, i32> {
+        let ty = SockType::try_from(ty).map_err(|_| libc::EINVAL)?;
+        let socket =
+            socket(AddressFamily::Unix, ty, SockFlag::SOCK_CLOEXEC, None).map_err(|e| e as i32)?;
+        match self {
+            SocketTarget::Abstract(name) => {
+                let addr = UnixAddr::new_abstract(name).map_err(|e| e as i32)?;
+                connect(socket.as_raw_fd(), &addr).map_err(|e| e as i32)?;
+            }
+            SocketTarget::Path(path) => {
+                let target = open_in_sandbox(pid, path)?;
+                let meta = File::from(target.try_clone().map_err(|_| libc::EMFILE)?)
+                    .metadata()
+                    .map_err(|e| e.raw_os_error().unwrap_or(libc::EIO))?;
+                if meta.mode() & libc::S_IFMT != libc::S_IFSOCK {
+                    return Err(libc::ECONNREFUSED);
+                }
+                // Connecting through the O_PATH fd reaches the socket just
+                // resolved, and keeps the address short enough for sun_path
+                // however deep the socket is.
+                let addr =
+                    UnixAddr::new(Path::new(&format!("/proc/self/fd/{}", target.as_raw_fd())))
+                        .map_err(|e| e as i32)?;
+                connect(socket.as_raw_fd(), &addr).map_err(|e| e as i32)?;
+            }
+        }
+        Ok(socket)
+    }
+}
+
+/// Lexically clean up a socket path: drop `.` components and resolve `..`
+/// against the components before it. A `..` at the start of a relative path
+/// is kept, for `open_in_sandbox` to refuse.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if matches!(normal.components().next_back(), Some(Component::Normal(_))) {
+                    normal.pop();
+                } else if !normal.has_root() {
+                    normal.push("..");
+                }
+            }
+            component => normal.push(component),
+        }
+    }
+    normal
+}
+
+/// Open `path` with O_PATH as process `pid` sees it: absolute paths from its
+/// root, relative ones beneath its cwd. Symlinks are refused along the whole
+/// path, since the host would otherwise follow them to wherever they point
+/// while the allowlist only saw the name.
+fn open_in_sandbox(pid: u32, path: &Path) -> Result<OwnedFd, i32> {
+    let (base, resolve) = if path.is_absolute() {
+        ("root", libc::RESOLVE_IN_ROOT)
+    } else {
+        ("cwd", libc::RESOLVE_BENEATH)
+    };
+    let dir = fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_PATH | libc::O_DIRECTORY)
+        .open(format!("/proc/{pid}/{base}"))
+        .map_err(|e| e.raw_os_error().unwrap_or(libc::ENOENT))?;
+    let relative = path.strip_prefix("/").unwrap_or(path);
+    let relative = if relative.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        relative
+    };
+    let relative = CString::new(relative.as_os_str().as_bytes()).map_err(|_| libc::EINVAL)?;
+
+    let mut how: libc::open_how = unsafe { std::mem::zeroed() };
+    how.flags = (libc::O_PATH | libc::O_CLOEXEC) as u64;
+    how.resolve = resolve | libc::RESOLVE_NO_SYMLINKS | libc::RESOLVE_NO_MAGICLINKS;
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_openat2,
+            dir.as_raw_fd(),
+            relative.as_ptr(),
+            &how as *const libc::open_how,
+            std::mem::size_of::<libc::open_how>(),
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error()
+            .raw_os_error()
+            .unwrap_or(libc::ENOENT));
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(fd as RawFd) })
+}
+
+impl std::fmt::Display for SocketTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SocketTarget::Path(path) => write!(f, "{}", path.display()),
+            SocketTarget::Abstract(name) => write!(f, "@{}", String::from_utf8_lossy(name)),
+        }
+    }
+}
+
+enum Answer {
+    Once,
+    Always,
+    Deny,
+}
+
+/// Ask on /dev/tty whether process `pid` may connect to `name`. Denies if
+/// there is no terminal.
+fn prompt_connect(name: &str, pid: u32) -> Answer {
+    let tty = match fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+    {
+        Ok(f) => f,
+        Err(_) => return Answer::Deny,
+    };
+    let mut tty_writer = io::BufWriter::new(tty.try_clone().unwrap());
+    let mut tty_reader = io::BufReader::new(tty);
+
+    let comm = fs::read_to_string(format!("/proc/{pid}/comm")).unwrap_or_default();
+    let _ = write!(
+        tty_writer,
+        "\najail: {} (pid {pid}) wants to connect to Unix socket {name}",
+        comm.trim()
+    );
+    loop {
+        let _ = write!(tty_writer, "\nAllow [o]nce, [a]lways, or [d]eny? ");
+        let _ = tty_writer.flush();
+        let mut input = String::new();
+        if !matches!(tty_reader.read_line(&mut input), Ok(n) if n > 0) {
+            return Answer::Deny;
+        }
+        match input.trim().to_lowercase().as_str() {
+            "o" | "once" => return Answer::Once,
+            "a" | "always" => return Answer::Always,
+            "d" | "deny" => return Answer::Deny,
+            _ => {
+                let _ = writeln!(
+                    tty_writer,
+                    "Please enter 'o' to allow once, 'a' to always allow, or 'd' to deny."
+                );
+            }
+        }
+    }
+}
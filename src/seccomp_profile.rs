@@ -0,0 +1,320 @@
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+use serde::de::IgnoredAny;
+
+use crate::seccomp::{self, Action, Arch, ArgCmp, CmpOp, Policy, Rule};
+
+/// A seccomp profile in the OCI runtime spec / Docker JSON format.
+///
+/// Unknown fields are rejected rather than ignored, so a profile relying on
+/// something ajail does not implement fails loudly.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct Profile {
+    default_action: String,
+    default_errno_ret: Option<u16>,
+    #[serde(default)]
+    architectures: Vec<String>,
+    /// Docker extension: native architectures and the compat ones they run
+    #[serde(default)]
+    arch_map: Vec<ArchMap>,
+    #[serde(default)]
+    flags: Vec<String>,
+    listener_path: Option<String>,
+    listener_metadata: Option<String>,
+    #[serde(default)]
+    syscalls: Vec<SyscallEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct ArchMap {
+    architecture: String,
+    #[serde(default)]
+    sub_architectures: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct SyscallEntry {
+    #[serde(default)]
+    names: Vec<String>,
+    /// Older Docker profiles name a single syscall per entry
+    name: Option<String>,
+    action: String,
+    errno_ret: Option<u16>,
+    #[serde(default)]
+    args: Vec<ProfileArg>,
+    #[serde(default)]
+    includes: Filter,
+    #[serde(default)]
+    excludes: Filter,
+    #[serde(default, rename = "comment")]
+    _comment: IgnoredAny,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct ProfileArg {
+    index: u8,
+    value: u64,
+    #[serde(default)]
+    value_two: u64,
+    op: String,
+}
+
+/// Docker extension: conditions under which an entry applies.
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct Filter {
+    #[serde(default)]
+    caps: Vec<String>,
+    #[serde(default)]
+    arches: Vec<String>,
+    min_kernel: Option<String>,
+}
+
+/// Map an `SCMP_ARCH_*` name. `None` for architectures ajail has no
+/// syscall table for, including x32, which the built-in filter always kills.
+/// Names libseccomp does not know are an error, so a typo does not leave an
+/// architecture unfiltered.
+fn scmp_arch(name: &str) -> Result<Option<Arch>, String> {
+    match name {
+        "SCMP_ARCH_X86_64" => Ok(Some(Arch::X86_64)),
+        "SCMP_ARCH_AARCH64" => Ok(Some(Arch::Aarch64)),
+        "SCMP_ARCH_X86" => Ok(Some(Arch::X86)),
+        "SCMP_ARCH_ARM" => Ok(Some(Arch::Arm)),
+        "SCMP_ARCH_NATIVE" if cfg!(target_arch = "x86_64") => Ok(Some(Arch::X86_64)),
+        "SCMP_ARCH_NATIVE" if cfg!(target_arch = "aarch64") => Ok(Some(Arch::Aarch64)),
+        "SCMP_ARCH_NATIVE"
+        | "SCMP_ARCH_X32"
+        | "SCMP_ARCH_LOONGARCH64"
+        | "SCMP_ARCH_M68K"
+        | "SCMP_ARCH_MIPS"
+        | "SCMP_ARCH_MIPS64"
+        | "SCMP_ARCH_MIPS64N32"
+        | "SCMP_ARCH_MIPSEL"
+        | "SCMP_ARCH_MIPSEL64"
+        | "SCMP_ARCH_MIPSEL64N32"
+        | "SCMP_ARCH_PPC"
+        | "SCMP_ARCH_PPC64"
+        | "SCMP_ARCH_PPC64LE"
+        | "SCMP_ARCH_S390"
+        | "SCMP_ARCH_S390X"
+        | "SCMP_ARCH_PARISC"
+        | "SCMP_ARCH_PARISC64"
+        | "SCMP_ARCH_RISCV64"
+        | "SCMP_ARCH_SH"
+        | "SCMP_ARCH_SHEB" => Ok(None),
+        _ => Err(format!("unknown architecture {name}")),
+    }
+}
+
+/// Map the Go-style architecture names used in `includes`/`excludes`.
+fn go_arch(name: &str) -> Result<Option<Arch>, String> {
+    match name {
+        "amd64" => Ok(Some(Arch::X86_64)),
+        "arm64" => Ok(Some(Arch::Aarch64)),
+        "x86" | "386" => Ok(Some(Arch::X86)),
+        "arm" => Ok(Some(Arch::Arm)),
+        "x32" | "loong64" | "mips" | "mipsle" | "mips64" | "mips64le" | "mips64n32"
+        | "mipsel64" | "mipsel64n32" | "ppc" | "ppc64" | "ppc64le" | "riscv64" | "s390"
+        | "s390x" => Ok(None),
+        _ => Err(format!("unknown architecture {name}")),
+    }
+}
+
+fn action(name: &str, errno: Option<u16>) -> Result<Action, String> {
+    match name {
+        "SCMP_ACT_ALLOW" => Ok(Action::Allow),
+        "SCMP_ACT_ERRNO" => Ok(Action::Errno(errno.unwrap_or(libc::EPERM as u16))),
+        // Killing the whole process is stricter than killing one thread.
+        "SCMP_ACT_KILL" | "SCMP_ACT_KILL_THREAD" | "SCMP_ACT_KILL_PROCESS" => Ok(Action::Kill),
+        "SCMP_ACT_LOG" => Ok(Action::Log),
+        _ => Err(format!("unsupported action {name}")),
+    }
+}
+
+fn arg_cmp(arg: &ProfileArg) -> Result<ArgCmp, String> {
+    let cmp = |op| ArgCmp {
+        index: arg.index,
+        op,
+        value: arg.value,
+        mask: u64::MAX,
+    };
+    match arg.op.as_str() {
+        "SCMP_CMP_EQ" => Ok(cmp(CmpOp::Eq)),
+        "SCMP_CMP_NE" => Ok(cmp(CmpOp::Ne)),
+        "SCMP_CMP_LT" => Ok(cmp(CmpOp::Lt)),
+        "SCMP_CMP_LE" => Ok(cmp(CmpOp::Le)),
+        "SCMP_CMP_GT" => Ok(cmp(CmpOp::Gt)),
+        "SCMP_CMP_GE" => Ok(cmp(CmpOp::Ge)),
+        // (arg & value) == valueTwo
+        "SCMP_CMP_MASKED_EQ" => Ok(ArgCmp {
+            index: arg.index,
+            op: CmpOp::Eq,
+            value: arg.value_two,
+            mask: arg.value,
+        }),
+        op => Err(format!("unsupported comparison {op}")),
+    }
+}
+
+/// `major.minor` of the running kernel.
+fn kernel_version() -> Option<(u32, u32)> {
+    let release = nix::sys::utsname::uname().ok()?;
+    parse_version(release.release().to_str()?)
+}
+
+fn parse_version(version: &str) -> Option<(u32, u32)> {
+    let mut parts = version.split(|c: char| !c.is_ascii_digit());
+    Some((parts.next()?.parse().ok()?, parts.next()?.parse().ok()?))
+}
+
+/// The architectures an entry applies to, or `None` if it does not apply
+/// to this sandbox at all.
+fn entry_arches(entry: &SyscallEntry) -> Result<Option<Vec<Arch>>, String> {
+    // The sandboxed process holds no capabilities.
+    if !entry.includes.caps.is_empty() {
+        eprintln!(
+            "ajail: warning: skipping seccomp profile entry for {}, it requires {}",
+            entry_names(entry).collect::<Vec<_>>().join(", "),
+            entry.includes.caps.join(", ")
+        );
+        return Ok(None);
+    }
+    if let Some(min) = &entry.includes.min_kernel {
+        let min = parse_version(min).ok_or_else(|| format!("invalid minKernel {min}"))?;
+        if kernel_version().is_some_and(|running| running < min) {
+            return Ok(None);
+        }
+    }
+    if entry.excludes.min_kernel.is_some() {
+        return Err("excludes.minKernel is not supported".to_string());
+    }
+
+    let mut arches: Vec<Arch> = if entry.includes.arches.is_empty() {
+        Arch::ALL.to_vec()
+    } else {
+        known_arches(&entry.includes.arches, go_arch)?
+    };
+    let excluded = known_arches(&entry.excludes.arches, go_arch)?;
+    arches.retain(|arch| !excluded.contains(arch));
+    Ok((!arches.is_empty()).then_some(arches))
+}
+
+/// The syscalls an entry names.
+fn entry_names(entry: &SyscallEntry) -> impl Iterator<Item = &str> {
+    entry.names.iter().chain(&entry.name).map(String::as_str)
+}
+
+/// Map architecture `names` with `map`, dropping duplicates and the ones
+/// ajail has no syscall table for.
+fn known_arches<'a>(
+    names: impl IntoIterator<Item = &'a String>,
+    map: fn(&str) -> Result<Option<Arch>, String>,
+) -> Result<Vec<Arch>, String> {
+    let mut arches = Vec::new();
+    for name in names {
+        if let Some(arch) = map(name)?
+            && !arches.contains(&arch)
+        {
+            arches.push(arch);
+        }
+    }
+    Ok(arches)
+}
+
+/// Reject a syscall with entries of different actions on a shared
+/// architecture. ajail's filter applies the first matching rule, while
+/// libseccomp picks the most specific one, so such a profile could behave
+/// differently here than under Docker.
+fn check_overlaps(rules: &[Rule]) -> Result<(), String> {
+    for (i, rule) in rules.iter().enumerate() {
+        let conflict = rules[..i].iter().any(|earlier| {
+            earlier.syscall == rule.syscall
+                && earlier.action != rule.action
+                && earlier.arches.iter().any(|arch| rule.arches.contains(arch))
+        });
+        if conflict {
+            return Err(format!(
+                "syscall {} has entries with different actions",
+                rule.syscall
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn convert(profile: Profile) -> Result<Policy, String> {
+    // TSYNC only matters for multi-threaded processes; the sandbox installs
+    // its filters before exec, single-threaded.
+    if let Some(flag) = profile
+        .flags
+        .iter()
+        .find(|f| *f != "SECCOMP_FILTER_FLAG_TSYNC")
+    {
+        return Err(format!("unsupported flag {flag}"));
+    }
+    if profile.listener_path.is_some() || profile.listener_metadata.is_some() {
+        return Err("seccomp notify listeners are not supported".to_string());
+    }
+
+    let mut arches = known_arches(
+        profile.architectures.iter().chain(
+            profile
+                .arch_map
+                .iter()
+                .flat_map(|m| std::iter::once(&m.architecture).chain(&m.sub_architectures)),
+        ),
+        scmp_arch,
+    )?;
+    if arches.is_empty() {
+        arches = Arch::NATIVE.to_vec();
+    }
+
+    let mut rules = Vec::new();
+    for entry in &profile.syscalls {
+        let action = action(&entry.action, entry.errno_ret)?;
+        let args = entry
+            .args
+            .iter()
+            .map(arg_cmp)
+            .collect::<Result<Vec<_>, _>>()?;
+        let Some(rule_arches) = entry_arches(entry)? else {
+            continue;
+        };
+        // Like runc, skip names this build does not know (other architectures,
+        // newer kernels) instead of failing on them.
+        for name in entry_names(entry) {
+            if seccomp::is_known_syscall(name) {
+                rules.push(Rule {
+                    syscall: name.to_string(),
+                    arches: rule_arches.clone(),
+                    args: args.clone(),
+                    action,
+                });
+            }
+        }
+    }
+
+    check_overlaps(&rules)?;
+
+    Ok(Policy {
+        rules,
+        default_action: action(&profile.default_action, profile.default_errno_ret)?,
+        arches,
+        foreign_arch_action: Action::Kill,
+    })
+}
+
+/// Load an OCI/Docker seccomp profile and translate it into a policy.
+pub fn load(path: &Path) -> Result<Policy, String> {
+    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let profile: Profile = serde_json::from_str(&content).map_err(|e| e.to_string())?;
+    let policy = convert(profile)?;
+    policy.compile()?;
+    Ok(policy)
+}
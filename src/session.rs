@@ -0,0 +1,446 @@
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use git2::{BranchType, Repository};
+use nix::errno::Errno;
+use nix::sys::signal::kill;
+use nix::unistd::Pid;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Options, state_dir};
+use crate::dangerous::Placeholders;
+use crate::overlay::{OverlayInfo, overlay_has_changes, show_overlay_changes};
+use crate::process::{handle_overlay_cleanup, handle_worktree_cleanup};
+use crate::util::repo_root;
+use crate::worktree::{
+    WorktreeInfo, discarded_sessions, expire_discarded, restore_discarded, show_worktree_diff,
+    worktree_has_changes,
+};
+use crate::{SessionsAction, WorktreeAction};
+
+/// What a session leaves on disk until its post-session phase is done. It
+/// is saved while the session runs, so a later run can resolve the session
+/// when ajail was killed before cleaning up.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionState {
+    pub id: String,
+    /// The ajail process running the session
+    pub pid: i32,
+    /// Unix time the session started, 0 if unknown
+    pub started: u64,
+    pub repo_root: PathBuf,
+    pub tmp_home: PathBuf,
+    pub worktree: Option<WorktreeInfo>,
+    pub overlay: Option<OverlayInfo>,
+    pub placeholders: Option<Placeholders>,
+    /// Kept on purpose with the `keep` action, so not offered again
+    #[serde(default)]
+    pub kept: bool,
+}
+
+/// Where session state files and recordings are kept.
+pub fn sessions_dir() -> PathBuf {
+    state_dir().join("sessions")
+}
+
+impl SessionState {
+    pub fn new(id: &str, repo_root: &Path, tmp_home: &Path) -> Self {
+        SessionState {
+            id: id.to_string(),
+            pid: std::process::id() as i32,
+            started: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            repo_root: repo_root.to_path_buf(),
+            tmp_home: tmp_home.to_path_buf(),
+            worktree: None,
+            overlay: None,
+            placeholders: None,
+            kept: false,
+        }
+    }
+
+    fn path(&self) -> PathBuf {
+        sessions_dir().join(format!("{}.json", self.id))
+    }
+
+    /// Write the state file, replacing it in one step.
+    pub fn save(&self) -> io::Result<()> {
+        let path = self.path();
+        fs::create_dir_all(sessions_dir())?;
+        let tmp = path.with_extension("json.tmp");
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&tmp)?;
+        file.write_all(&serde_json::to_vec_pretty(self).map_err(io::Error::other)?)?;
+        fs::rename(&tmp, &path)
+    }
+
+    /// Forget the session once its worktree and overlay are gone. A
+    /// worktree kept after a failed merge keeps the state file, so a later
+    /// run offers it again; one `kept` on purpose is only marked as such.
+    pub fn finish(&self, kept: bool) {
+        let worktree_left = self
+            .worktree
+            .as_ref()
+            .is_some_and(|w| w.worktree_path.exists());
+        let overlay_left = self
+            .overlay
+            .as_ref()
+            .is_some_and(|o| o.session_dir.exists());
+        if !worktree_left && !overlay_left {
+            let _ = fs::remove_file(self.path());
+        } else if kept && !self.kept {
+            let _ = SessionState {
+                kept,
+                ..self.clone()
+            }
+            .save();
+        }
+    }
+
+    /// Whether the ajail process that ran the session is still alive.
+    fn is_running(&self) -> bool {
+        self.pid > 0 && !matches!(kill(Pid::from_raw(self.pid), None), Err(Errno::ESRCH))
+    }
+
+    fn age(&self) -> Option<String> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        (self.started > 0).then(|| format!("{} ago", format_age(now.saturating_sub(self.started))))
+    }
+
+    /// The session's worktree, or just its branch once the worktree
+    /// directory is gone.
+    fn worktree(&self) -> Option<&WorktreeInfo> {
+        self.worktree.as_ref().filter(|w| {
+            w.worktree_path.exists()
+                || Repository::open(&w.original_repo)
+                    .is_ok_and(|r| r.find_branch(&w.branch_name, BranchType::Local).is_ok())
+        })
+    }
+
+    fn overlay(&self) -> Option<&OverlayInfo> {
+        self.overlay.as_ref().filter(|o| o.session_dir.exists())
+    }
+
+    fn has_changes(&self) -> bool {
+        self.worktree().is_some_and(worktree_has_changes)
+            || self.overlay().is_some_and(overlay_has_changes)
+    }
+
+    /// Where the session's changes are, one line each.
+    fn locations(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        if let Some(info) = self.worktree() {
+            if info.worktree_path.exists() {
+                lines.push(format!(
+                    "worktree: {} (branch: {})",
+                    info.worktree_path.display(),
+                    info.branch_name
+                ));
+            } else {
+                lines.push(format!("branch: {}", info.branch_name));
+            }
+        }
+        if let Some(info) = self.overlay() {
+            lines.push(format!("overlay: {}", info.upper_dir.display()));
+        }
+        lines
+    }
+
+    /// Remove what the session left that holds no work.
+    fn remove_temporary(&self) {
+        let _ = fs::remove_dir_all(&self.tmp_home);
+        if let Some(ref placeholders) = self.placeholders {
+            placeholders.remove();
+        }
+    }
+
+    /// Clean up what needs no decision, then offer the user to review the
+    /// session's changes.
+    fn recover(&self, options: &Options) {
+        self.remove_temporary();
+        if self.worktree().is_none() && self.overlay().is_none() {
+            self.finish(false);
+            return;
+        }
+
+        eprintln!(
+            "ajail: session {} from {} did not finish cleaning up.",
+            self.id,
+            self.age().unwrap_or_else(|| "earlier".to_string())
+        );
+        for line in self.locations() {
+            eprintln!("  {line}");
+        }
+        if !prompt_review() {
+            return;
+        }
+        self.resolve(&WorktreeAction::Prompt, options);
+    }
+
+    /// Merge, discard or prompt for the session's changes, as after a
+    /// session. Returns whether nothing is left.
+    fn resolve(&self, action: &WorktreeAction, options: &Options) -> bool {
+        self.remove_temporary();
+        let kept_worktree = handle_worktree_cleanup(&self.worktree().cloned(), action, options);
+        let kept_overlay = handle_overlay_cleanup(&self.overlay().cloned(), action);
+        self.finish(kept_worktree || kept_overlay);
+        self.worktree().is_none() && self.overlay().is_none()
+    }
+}
+
+fn format_age(secs: u64) -> String {
+    match secs {
+        0..120 => format!("{secs} seconds"),
+        120..7200 => format!("{} minutes", secs / 60),
+        7200..172800 => format!("{} hours", secs / 3600),
+        _ => format!("{} days", secs / 86400),
+    }
+}
+
+/// State files of all sessions, running or not. Unreadable files are
+/// skipped.
+pub fn load_all() -> Vec<SessionState> {
+    let Ok(entries) = fs::read_dir(sessions_dir()) else {
+        return Vec::new();
+    };
+    let mut sessions: Vec<SessionState> = entries
+        .flatten()
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|e| serde_json::from_slice(&fs::read(e.path()).ok()?).ok())
+        .collect();
+    sessions.sort_by_key(|s| s.started);
+    sessions
+}
+
+/// Offer to resolve sessions in `repo_root` whose ajail died before its
+/// post-session phase.
+pub fn recover_orphans(repo_root: &Path, options: &Options) {
+    for session in load_all() {
+        if session.repo_root == repo_root && !session.kept && !session.is_running() {
+            session.recover(options);
+        }
+    }
+}
+
+fn is_session_id(id: &str) -> bool {
+    id.len() == 8 && id.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Sessions in `repo_root`: those with a state file, and `ajail-<id>`
+/// branches left behind without one.
+fn find_sessions(repo_root: &Path) -> Vec<SessionState> {
+    let mut sessions: Vec<SessionState> = load_all()
+        .into_iter()
+        .filter(|s| s.repo_root == repo_root)
+        .collect();
+    let Ok(repo) = Repository::open(repo_root) else {
+        return sessions;
+    };
+    let Ok(branches) = repo.branches(Some(BranchType::Local)) else {
+        return sessions;
+    };
+    let head = repo.head().ok().and_then(|h| h.target());
+    for (branch, _) in branches.flatten() {
+        let Some(name) = branch.name().ok().flatten() else {
+            continue;
+        };
+        let Some(id) = name.strip_prefix("ajail-").filter(|id| is_session_id(id)) else {
+            continue;
+        };
+        let Some(tip) = branch.get().target() else {
+            continue;
+        };
+        if sessions.iter().any(|s| s.id == id) {
+            continue;
+        }
+        // The branch started where it meets the current HEAD
+        let original_head = head
+            .and_then(|head| repo.merge_base(head, tip).ok())
+            .unwrap_or(tip);
+        let worktree_path = repo
+            .find_worktree(&format!("ajail-worktree-{id}"))
+            .map(|w| w.path().to_path_buf())
+            .unwrap_or_else(|_| env::temp_dir().join(format!("ajail-worktree-{id}")));
+        let mut session =
+            SessionState::new(id, repo_root, &env::temp_dir().join(format!("ajail-{id}")));
+        session.pid = 0;
+        session.started = 0;
+        session.worktree = Some(WorktreeInfo {
+            worktree_path,
+            branch_name: name.to_string(),
+            original_head: original_head.to_string(),
+            original_branch: None,
+            original_repo: repo_root.to_path_buf(),
+            snapshot: None,
+        });
+        sessions.push(session);
+    }
+    sessions
+}
+
+/// `ajail sessions`: manage the sessions of the repo in the current
+/// directory.
+pub fn run_command(action: &SessionsAction, options: &Options) -> ExitCode {
+    let project_dir = env::current_dir().expect("Failed to get current directory");
+    let repo_root = repo_root(&project_dir);
+    let repo_root = fs::canonicalize(&repo_root).unwrap_or(repo_root);
+    let sessions = find_sessions(&repo_root);
+
+    let (id, action) = match action {
+        SessionsAction::List => {
+            list(&sessions, &repo_root);
+            return ExitCode::SUCCESS;
+        }
+        SessionsAction::Clean => {
+            clean(&sessions, &repo_root, options);
+            return ExitCode::SUCCESS;
+        }
+        SessionsAction::Restore { id } => {
+            let id = id.strip_prefix("ajail-").unwrap_or(id);
+            return match restore_discarded(&repo_root, id) {
+                Ok(branch) => {
+                    eprintln!(
+                        "Restored session {id} as branch {branch} (ajail sessions merge|discard {id})"
+                    );
+                    ExitCode::SUCCESS
+                }
+                Err(e) => {
+                    eprintln!("{e}");
+                    ExitCode::FAILURE
+                }
+            };
+        }
+        SessionsAction::Show { id } => (id, None),
+        SessionsAction::Merge { id } => (id, Some(WorktreeAction::Merge)),
+        SessionsAction::Discard { id } => (id, Some(WorktreeAction::Discard)),
+    };
+
+    let id = id.strip_prefix("ajail-").unwrap_or(id);
+    let Some(session) = sessions.iter().find(|s| s.id == id) else {
+        eprintln!("No session {id} in {}", repo_root.display());
+        return ExitCode::FAILURE;
+    };
+    let Some(action) = action else {
+        show(session);
+        return ExitCode::SUCCESS;
+    };
+    if session.is_running() {
+        eprintln!("Session {id} is still running (pid {})", session.pid);
+        return ExitCode::FAILURE;
+    }
+    if session.resolve(&action, options) {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+fn status(session: &SessionState) -> &'static str {
+    if session.pid == 0 {
+        "branch only"
+    } else if session.is_running() {
+        "running"
+    } else if session.kept {
+        "kept"
+    } else {
+        "unfinished"
+    }
+}
+
+fn print_summary(session: &SessionState) {
+    let mut notes = vec![status(session).to_string()];
+    notes.extend(session.age());
+    if !session.has_changes() {
+        notes.push("no changes".to_string());
+    }
+    println!("{}  {}", session.id, notes.join(", "));
+    for line in session.locations() {
+        println!("  {line}");
+    }
+}
+
+fn list(sessions: &[SessionState], repo_root: &Path) {
+    let discarded = discarded_sessions(repo_root);
+    if sessions.is_empty() && discarded.is_empty() {
+        eprintln!("No sessions in {}", repo_root.display());
+    }
+    for session in sessions {
+        print_summary(session);
+    }
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    for (id, time) in discarded {
+        let age = format_age(now.saturating_sub(time as u64));
+        println!("{id}  discarded, {age} ago");
+        println!("  ref: refs/ajail/discarded/{id} (ajail sessions restore {id})");
+    }
+}
+
+fn show(session: &SessionState) {
+    print_summary(session);
+    if let Some(info) = session.worktree() {
+        eprintln!("\n--- Worktree changes ---");
+        show_worktree_diff(info);
+    }
+    if let Some(info) = session.overlay() {
+        eprintln!("\n--- Overlay changes ---");
+        show_overlay_changes(info);
+    }
+}
+
+/// Remove sessions that hold no changes, and point at the rest. Discarded
+/// sessions past their retention are removed too.
+fn clean(sessions: &[SessionState], repo_root: &Path, options: &Options) {
+    for id in expire_discarded(repo_root, options.discard_retention_days) {
+        eprintln!("Removed discarded session {id}");
+    }
+    for session in sessions.iter().filter(|s| !s.is_running()) {
+        if session.has_changes() {
+            eprintln!(
+                "Keeping session {id}, it has changes (ajail sessions merge|discard {id})",
+                id = session.id
+            );
+            continue;
+        }
+        session.resolve(&WorktreeAction::Discard, options);
+        eprintln!("Removed session {}", session.id);
+    }
+}
+
+/// Ask on the terminal whether to review an unfinished session now.
+fn prompt_review() -> bool {
+    let Ok(tty) = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+    else {
+        eprintln!("Run ajail on a terminal to review it.");
+        return false;
+    };
+    let mut tty_writer = io::BufWriter::new(tty.try_clone().unwrap());
+    let mut tty_reader = io::BufReader::new(tty);
+
+    let _ = write!(tty_writer, "Review its changes now? [y/N] ");
+    let _ = tty_writer.flush();
+    let mut input = String::new();
+    let _ = tty_reader.read_line(&mut input);
+    let review = matches!(input.trim().to_lowercase().as_str(), "y" | "yes");
+    if !review {
+        eprintln!("Keeping it for later.");
+    }
+    review
+}
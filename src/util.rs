@@ -1,5 +1,11 @@
+use std::env;
+use std::io::{self, IoSlice, IoSliceMut};
+use std::os::fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
+use std::os::unix::net::UnixStream;
 use std::path::{Path, PathBuf};
 
+use nix::sys::socket::{ControlMessage, ControlMessageOwned, MsgFlags, recvmsg, sendmsg};
+
 pub fn random_hex(len: usize) -> String {
     let mut buf = vec![0u8; len.div_ceil(2)];
     getrandom::fill(&mut buf).expect("Failed to get random bytes");
@@ -12,3 +18,55 @@ pub fn repo_root(project_dir: &Path) -> PathBuf {
         .and_then(|repo| repo.workdir().map(|p| p.to_path_buf()))
         .unwrap_or_else(|| project_dir.to_path_buf())
 }
+
+/// Expand a leading `~` to $HOME and make the path absolute against the
+/// current directory.
+pub fn expand_path(path: &str) -> PathBuf {
+    let home = env::var_os("HOME").map(PathBuf::from).unwrap_or_default();
+    let expanded = match path.strip_prefix('~') {
+        Some("") => home,
+        Some(rest) if rest.starts_with('/') => home.join(&rest[1..]),
+        _ => PathBuf::from(path),
+    };
+    std::path::absolute(&expanded).unwrap_or(expanded)
+}
+
+/// Send a file descriptor to the other end of `channel` with SCM_RIGHTS.
+pub fn send_fd(channel: &UnixStream, fd: BorrowedFd) -> io::Result<()> {
+    let fds = [fd.as_raw_fd()];
+    sendmsg::<()>(
+        channel.as_raw_fd(),
+        &[IoSlice::new(&[0])],
+        &[ControlMessage::ScmRights(&fds)],
+        MsgFlags::empty(),
+        None,
+    )?;
+    Ok(())
+}
+
+/// Receive a file descriptor sent by `send_fd`. Fails with `UnexpectedEof`
+/// if the sandbox exited before sending it; `what` names it in the error.
+pub fn receive_fd(channel: &UnixStream, what: &str) -> io::Result<OwnedFd> {
+    let mut buf = [0u8; 1];
+    let mut iov = [IoSliceMut::new(&mut buf)];
+    let mut cmsg_buf = nix::cmsg_space!([std::os::fd::RawFd; 1]);
+    let msg = recvmsg::<()>(
+        channel.as_raw_fd(),
+        &mut iov,
+        Some(&mut cmsg_buf),
+        MsgFlags::MSG_CMSG_CLOEXEC,
+    )?;
+
+    for cmsg in msg.cmsgs()? {
+        if let ControlMessageOwned::ScmRights(fds) = cmsg
+            && let Some(&fd) = fds.first()
+        {
+            return Ok(unsafe { OwnedFd::from_raw_fd(fd) });
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        format!("sandbox exited before handing over the {what}"),
+    ))
+}
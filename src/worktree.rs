@@ -1,29 +1,116 @@
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use git2::{BranchType, DiffStatsFormat, Oid, Repository};
+use clap::ValueEnum;
+use git2::build::CheckoutBuilder;
+use git2::{
+    ApplyLocation, BranchType, Diff, DiffOptions, DiffStatsFormat, ErrorCode, IndexAddOption, Oid,
+    RebaseOptions, Repository, ResetType, Signature, StashApplyOptions, StatusOptions,
+};
+use serde::{Deserialize, Serialize};
 
-use crate::WorktreeAction;
+use crate::{CarryChanges, DirtyCheckout, WorktreeAction};
 use std::env;
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct WorktreeInfo {
     pub worktree_path: PathBuf,
     pub branch_name: String,
     pub original_head: String,
+    /// Branch checked out in the original repo when the session started
+    #[serde(default)]
+    pub original_branch: Option<String>,
     pub original_repo: PathBuf,
+    /// Uncommitted changes carried over from the original checkout
+    #[serde(default)]
+    pub snapshot: Option<Snapshot>,
 }
 
-pub fn create_worktree(repo_root: &Path, session_id: &str) -> Result<WorktreeInfo, String> {
+/// The original checkout's uncommitted state, as tree objects. The review
+/// after the session is relative to `tree`, so it shows only the agent's
+/// work.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// The index
+    pub staged: String,
+    /// The working directory
+    pub tree: String,
+    /// Whether `tree` includes untracked files
+    pub untracked: bool,
+}
+
+/// Tree of the working directory as `git add -u` (or `git add -A` with
+/// `untracked`) would stage it, built without writing the index.
+fn working_tree(repo: &Repository, untracked: bool) -> Result<Oid, git2::Error> {
+    let mut index = repo.index()?;
+    let result = (|| {
+        index.update_all(["*"], None)?;
+        if untracked {
+            index.add_all(["*"], IndexAddOption::DEFAULT, None)?;
+        }
+        index.write_tree()
+    })();
+    // Drop the in-memory changes
+    index.read(true)?;
+    result
+}
+
+/// Snapshot the uncommitted changes of `repo`, or `None` when it is clean.
+fn snapshot_changes(
+    repo: &Repository,
+    head: Oid,
+    carry: CarryChanges,
+) -> Result<Option<Snapshot>, git2::Error> {
+    let untracked = match carry {
+        CarryChanges::None => return Ok(None),
+        CarryChanges::Tracked => false,
+        CarryChanges::All => true,
+    };
+    let head_tree = repo.find_commit(head)?.tree_id();
+    let staged = repo.index()?.write_tree()?;
+    let tree = working_tree(repo, untracked)?;
+    if staged == head_tree && tree == head_tree {
+        return Ok(None);
+    }
+    Ok(Some(Snapshot {
+        staged: staged.to_string(),
+        tree: tree.to_string(),
+        untracked,
+    }))
+}
+
+/// Check out the snapshot in a fresh worktree: the working directory as
+/// it was, with the same changes staged.
+fn apply_snapshot(worktree_path: &Path, snapshot: &Snapshot) -> Result<(), git2::Error> {
+    let repo = Repository::open(worktree_path)?;
+    let tree = repo.find_tree(Oid::from_str(&snapshot.tree)?)?;
+    repo.checkout_tree(tree.as_object(), Some(CheckoutBuilder::new().force()))?;
+    let staged = repo.find_tree(Oid::from_str(&snapshot.staged)?)?;
+    let mut index = repo.index()?;
+    index.read_tree(&staged)?;
+    index.write()
+}
+
+pub fn create_worktree(
+    repo_root: &Path,
+    session_id: &str,
+    carry: CarryChanges,
+) -> Result<WorktreeInfo, String> {
     let repo = Repository::open(repo_root).map_err(|e| format!("Failed to open repo: {e}"))?;
 
     let head = repo
         .head()
         .map_err(|_| "Failed to get HEAD. Is this a git repo with at least one commit?")?;
-    let original_head = head
-        .target()
-        .ok_or("HEAD is not a direct reference")?
-        .to_string();
+    let head_oid = head.target().ok_or("HEAD is not a direct reference")?;
+    let original_head = head_oid.to_string();
+    let original_branch = head
+        .is_branch()
+        .then(|| head.shorthand().map(String::from))
+        .flatten();
+    let snapshot = snapshot_changes(&repo, head_oid, carry)
+        .map_err(|e| format!("Failed to snapshot uncommitted changes: {e}"))?;
 
     let branch_name = format!("ajail-{session_id}");
     let worktree_path = env::temp_dir().join(format!("ajail-worktree-{session_id}"));
@@ -48,18 +135,38 @@ pub fn create_worktree(repo_root: &Path, session_id: &str) -> Result<WorktreeInf
         ));
     }
 
-    Ok(WorktreeInfo {
+    let info = WorktreeInfo {
         worktree_path,
         branch_name,
         original_head,
+        original_branch,
         original_repo: repo_root.to_path_buf(),
-    })
+        snapshot,
+    };
+    if let Some(ref snapshot) = info.snapshot
+        && let Err(e) = apply_snapshot(&info.worktree_path, snapshot)
+    {
+        cleanup_worktree(&info);
+        return Err(format!("Failed to carry uncommitted changes over: {e}"));
+    }
+    Ok(info)
+}
+
+/// Tip of the session branch, for sessions whose worktree directory is gone.
+fn branch_tip(info: &WorktreeInfo) -> Option<Oid> {
+    let repo = Repository::open(&info.original_repo).ok()?;
+    let branch = repo
+        .find_branch(&info.branch_name, BranchType::Local)
+        .ok()?;
+    branch.get().target()
 }
 
 pub fn worktree_has_changes(info: &WorktreeInfo) -> bool {
     let repo = match Repository::open(&info.worktree_path) {
         Ok(r) => r,
-        Err(_) => return false,
+        Err(_) => {
+            return branch_tip(info).is_some_and(|tip| tip.to_string() != info.original_head);
+        }
     };
 
     // Check if HEAD has moved beyond original
@@ -74,14 +181,22 @@ pub fn worktree_has_changes(info: &WorktreeInfo) -> bool {
         return true;
     }
 
-    // Check for uncommitted changes
+    // Check for uncommitted changes, beyond those carried over
+    if let Some(ref snapshot) = info.snapshot {
+        return working_tree(&repo, snapshot.untracked)
+            .is_ok_and(|tree| tree.to_string() != snapshot.tree);
+    }
     repo.statuses(None).map(|s| !s.is_empty()).unwrap_or(false)
 }
 
 pub fn show_worktree_diff(info: &WorktreeInfo) {
-    let repo = match Repository::open(&info.worktree_path) {
-        Ok(r) => r,
-        Err(_) => return,
+    // Without the worktree, only the branch is left to show
+    let (repo, tip, in_worktree) = match Repository::open(&info.worktree_path) {
+        Ok(r) => (r, "HEAD".to_string(), true),
+        Err(_) => match Repository::open(&info.original_repo) {
+            Ok(r) => (r, format!("refs/heads/{}", info.branch_name), false),
+            Err(_) => return,
+        },
     };
 
     let original_oid = match Oid::from_str(&info.original_head) {
@@ -92,7 +207,10 @@ pub fn show_worktree_diff(info: &WorktreeInfo) {
     // Show commits: equivalent of `git log --oneline <original>..HEAD`
     if let Ok(mut revwalk) = repo.revwalk() {
         revwalk.set_sorting(git2::Sort::REVERSE).ok();
-        if revwalk.push_range(&format!("{original_oid}..HEAD")).is_ok() {
+        if revwalk
+            .push_range(&format!("{original_oid}..{tip}"))
+            .is_ok()
+        {
             for oid in revwalk.flatten() {
                 if let Ok(commit) = repo.find_commit(oid) {
                     let short = &commit.id().to_string()[..7];
@@ -103,10 +221,25 @@ pub fn show_worktree_diff(info: &WorktreeInfo) {
         }
     }
 
-    // Show diff stats: equivalent of `git diff --stat <original>`
-    if let Ok(original_commit) = repo.find_commit(original_oid)
-        && let Ok(original_tree) = original_commit.tree()
-        && let Ok(diff) = repo.diff_tree_to_workdir_with_index(Some(&original_tree), None)
+    // Show diff stats: equivalent of `git diff --stat <original>`, or
+    // against the carried-over changes
+    let snapshot = info.snapshot.as_ref().filter(|_| in_worktree);
+    let diff = repo
+        .find_commit(original_oid)
+        .and_then(|c| c.tree())
+        .and_then(|original_tree| {
+            if let Some(snapshot) = snapshot {
+                let base = repo.find_tree(Oid::from_str(&snapshot.tree)?)?;
+                let current = repo.find_tree(working_tree(&repo, snapshot.untracked)?)?;
+                repo.diff_tree_to_tree(Some(&base), Some(&current), None)
+            } else if in_worktree {
+                repo.diff_tree_to_workdir_with_index(Some(&original_tree), None)
+            } else {
+                let tip_tree = repo.revparse_single(&tip)?.peel_to_tree()?;
+                repo.diff_tree_to_tree(Some(&original_tree), Some(&tip_tree), None)
+            }
+        });
+    if let Ok(diff) = diff
         && let Ok(stats) = diff.stats()
         && let Ok(buf) = stats.to_buf(DiffStatsFormat::FULL, 80)
     {
@@ -114,7 +247,8 @@ pub fn show_worktree_diff(info: &WorktreeInfo) {
     }
 }
 
-pub fn prompt_worktree_action() -> WorktreeAction {
+/// Ask which of `choices` to take, each picked by its first letter.
+pub fn prompt_worktree_action(choices: &[WorktreeAction]) -> WorktreeAction {
     use std::io::{BufRead, Write};
 
     // Read from /dev/tty for interactive input (terminal may have been used by the sandboxed process)
@@ -132,25 +266,241 @@ pub fn prompt_worktree_action() -> WorktreeAction {
     let mut tty_writer = std::io::BufWriter::new(tty.try_clone().unwrap());
     let mut tty_reader = std::io::BufReader::new(tty);
 
+    let names: Vec<String> = choices
+        .iter()
+        .filter_map(|c| c.to_possible_value())
+        .map(|v| v.get_name().to_string())
+        .collect();
+    let labels: Vec<String> = names
+        .iter()
+        .map(|name| format!("[{}]{}", &name[..1], &name[1..]))
+        .collect();
+    let (last, rest) = labels.split_last().expect("no choices");
+    let question = format!("{} or {last}? ", rest.join(", "));
+
     loop {
-        let _ = write!(tty_writer, "\n[m]erge or [d]iscard? ");
+        let _ = write!(tty_writer, "\n{question}");
         let _ = tty_writer.flush();
         let mut input = String::new();
-        if tty_reader.read_line(&mut input).is_err() {
+        if !matches!(tty_reader.read_line(&mut input), Ok(n) if n > 0) {
             return WorktreeAction::Discard;
         }
-        match input.trim().to_lowercase().as_str() {
-            "m" | "merge" => return WorktreeAction::Merge,
-            "d" | "discard" => return WorktreeAction::Discard,
-            _ => {
-                let _ = writeln!(tty_writer, "Please enter 'm' to merge or 'd' to discard.");
+        let input = input.trim().to_lowercase();
+        let picked = names
+            .iter()
+            .position(|name| !input.is_empty() && (*name == input || name[..1] == input));
+        match picked {
+            Some(i) => return choices[i],
+            None => {
+                let _ = writeln!(
+                    tty_writer,
+                    "Please enter one of: {}.",
+                    names
+                        .iter()
+                        .map(|name| format!("'{}'", &name[..1]))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+        }
+    }
+}
+
+/// Ask for a line of text on the terminal, with `default` for an empty
+/// answer. `None` without a terminal.
+pub fn prompt_text(question: &str, default: &str) -> Option<String> {
+    use std::io::{BufRead, Write};
+
+    let tty = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .ok()?;
+    let mut tty_writer = std::io::BufWriter::new(tty.try_clone().ok()?);
+    let mut tty_reader = std::io::BufReader::new(tty);
+    let _ = write!(tty_writer, "{question} [{default}]: ");
+    let _ = tty_writer.flush();
+    let mut input = String::new();
+    tty_reader.read_line(&mut input).ok()?;
+    match input.trim() {
+        "" => Some(default.to_string()),
+        answer => Some(answer.to_string()),
+    }
+}
+
+/// The agent's uncommitted changes in the worktree, relative to the
+/// carried-over snapshot or to HEAD, or `None` when there are none.
+fn uncommitted_diff<'r>(
+    repo: &'r Repository,
+    info: &WorktreeInfo,
+) -> Result<Option<Diff<'r>>, git2::Error> {
+    let base = match info.snapshot {
+        Some(ref snapshot) => Oid::from_str(&snapshot.tree)?,
+        None => repo.head()?.peel_to_tree()?.id(),
+    };
+    let current = working_tree(repo, true)?;
+    if current == base {
+        return Ok(None);
+    }
+    let mut opts = DiffOptions::new();
+    opts.show_binary(true);
+    let diff = repo.diff_tree_to_tree(
+        Some(&repo.find_tree(base)?),
+        Some(&repo.find_tree(current)?),
+        Some(&mut opts),
+    )?;
+    Ok(Some(diff))
+}
+
+/// Tree of HEAD plus the agent's uncommitted changes in the worktree,
+/// without those carried over from the original checkout, which is still
+/// there. `None` when there are no uncommitted changes.
+fn uncommitted_tree(repo: &Repository, info: &WorktreeInfo) -> Result<Option<Oid>, git2::Error> {
+    let diff = match uncommitted_diff(repo, info)? {
+        Some(diff) => diff,
+        None => return Ok(None),
+    };
+    let head = repo.head()?.peel_to_tree()?;
+    let tree = match info.snapshot {
+        Some(_) => match repo.apply_to_tree(&head, &diff, None) {
+            Ok(mut index) => index.write_tree_to(repo)?,
+            Err(e) => {
+                eprintln!(
+                    "Warning: the uncommitted changes do not apply without the ones carried over ({e}), committing both."
+                );
+                working_tree(repo, true)?
+            }
+        },
+        None => working_tree(repo, true)?,
+    };
+    Ok(Some(tree))
+}
+
+/// Commit the agent's uncommitted changes on the session branch, so merging
+/// takes them along instead of removing them with the worktree.
+pub fn commit_uncommitted(info: &WorktreeInfo) -> bool {
+    // Without the worktree there is nothing uncommitted
+    let repo = match Repository::open(&info.worktree_path) {
+        Ok(r) => r,
+        Err(_) => return true,
+    };
+    let session_id = info.branch_name.trim_start_matches("ajail-");
+
+    let result = (|| -> Result<bool, git2::Error> {
+        let tree_oid = match uncommitted_tree(&repo, info)? {
+            Some(tree) => tree,
+            None => return Ok(false),
+        };
+        let head = repo.head()?.peel_to_commit()?;
+        let tree = repo.find_tree(tree_oid)?;
+        let sig = signature(&repo)?;
+        let msg = format!(
+            "ajail: uncommitted changes from session {session_id}\n\nAjail-Session: {session_id}"
+        );
+        repo.commit(Some("HEAD"), &sig, &sig, &msg, &tree, &[&head])?;
+        let mut index = repo.index()?;
+        index.read_tree(&tree)?;
+        index.write()?;
+        Ok(true)
+    })();
+
+    match result {
+        Ok(committed) => {
+            if committed {
+                eprintln!(
+                    "Committed uncommitted worktree changes on {}.",
+                    info.branch_name
+                );
+            }
+            true
+        }
+        Err(e) => {
+            eprintln!(
+                "Failed to commit uncommitted worktree changes: {e}. Worktree preserved at: {}",
+                info.worktree_path.display()
+            );
+            false
+        }
+    }
+}
+
+/// Apply the agent's uncommitted changes to the original checkout, where
+/// they stay uncommitted.
+pub fn carry_uncommitted(info: &WorktreeInfo) -> bool {
+    let worktree = match Repository::open(&info.worktree_path) {
+        Ok(r) => r,
+        Err(_) => return true,
+    };
+
+    let result = (|| -> Result<bool, git2::Error> {
+        let diff = match uncommitted_diff(&worktree, info)? {
+            Some(diff) => diff,
+            None => return Ok(false),
+        };
+        let repo = Repository::open(&info.original_repo)?;
+        repo.apply(&diff, ApplyLocation::WorkDir, None)?;
+        Ok(true)
+    })();
+
+    match result {
+        Ok(carried) => {
+            if carried {
+                eprintln!("Carried uncommitted worktree changes over to the original checkout.");
             }
+            true
         }
+        Err(e) => {
+            eprintln!(
+                "Failed to carry uncommitted worktree changes over: {e}. Worktree preserved at: {}",
+                info.worktree_path.display()
+            );
+            false
+        }
+    }
+}
+
+/// Tell the user when the original checkout moved on or switched branches
+/// while the agent was working, since the merge goes into whatever is
+/// checked out now.
+fn report_moved_head(repo: &Repository, info: &WorktreeInfo) {
+    let Ok(head) = repo.head() else {
+        return;
+    };
+    let current = head.shorthand().filter(|_| head.is_branch());
+    let short = |oid: &str| oid.get(..7).unwrap_or(oid).to_string();
+    let head_oid = head.target().map(|oid| oid.to_string()).unwrap_or_default();
+
+    if let Some(ref original) = info.original_branch
+        && current != Some(original.as_str())
+    {
+        let now = match current {
+            Some(branch) => format!("branch {branch}"),
+            None => format!("a detached HEAD at {}", short(&head_oid)),
+        };
+        eprintln!(
+            "Note: the original checkout switched from branch {original} to {now} during the session; merging into {now}."
+        );
+    } else if head_oid != info.original_head {
+        eprintln!(
+            "Note: {} moved from {} to {} during the session; merging on top of it.",
+            current.unwrap_or("HEAD"),
+            short(&info.original_head),
+            short(&head_oid)
+        );
     }
 }
 
-pub fn merge_worktree(info: &WorktreeInfo) -> bool {
-    let repo = match Repository::open(&info.original_repo) {
+/// Whether the original checkout has uncommitted changes to tracked files.
+/// Untracked files are left alone: a merge that would overwrite one fails
+/// before touching anything.
+fn checkout_is_dirty(repo: &Repository) -> Result<bool, git2::Error> {
+    let mut opts = StatusOptions::new();
+    opts.include_untracked(false).include_ignored(false);
+    Ok(!repo.statuses(Some(&mut opts))?.is_empty())
+}
+
+pub fn merge_worktree(info: &WorktreeInfo, dirty_checkout: DirtyCheckout) -> bool {
+    let mut repo = match Repository::open(&info.original_repo) {
         Ok(r) => r,
         Err(e) => {
             eprintln!("Failed to open repo: {e}");
@@ -173,7 +523,63 @@ pub fn merge_worktree(info: &WorktreeInfo) -> bool {
             return false;
         }
     };
+    drop(branch_ref);
 
+    report_moved_head(&repo, info);
+
+    let dirty = match checkout_is_dirty(&repo) {
+        Ok(dirty) => dirty,
+        Err(e) => {
+            eprintln!("Failed to get the status of the original checkout: {e}");
+            return false;
+        }
+    };
+    if !dirty {
+        return merge_into_checkout(&repo, info, their_oid);
+    }
+
+    let session_id = info.branch_name.trim_start_matches("ajail-");
+    match dirty_checkout {
+        DirtyCheckout::Abort => {
+            eprintln!(
+                "The original checkout has uncommitted changes, not merging. Commit or stash them and run `ajail sessions merge {session_id}`, or merge with --dirty-checkout stash. Worktree preserved at: {}",
+                info.worktree_path.display()
+            );
+            false
+        }
+        DirtyCheckout::NoCheckout => merge_without_checkout(&repo, info, their_oid),
+        DirtyCheckout::Stash => {
+            let stashed = repo.signature().and_then(|sig| {
+                repo.stash_save(
+                    &sig,
+                    &format!("ajail: before merging session {session_id}"),
+                    None,
+                )
+            });
+            if let Err(e) = stashed {
+                eprintln!(
+                    "Failed to stash the uncommitted changes: {e}. Worktree preserved at: {}",
+                    info.worktree_path.display()
+                );
+                return false;
+            }
+            eprintln!("Stashed the uncommitted changes of the original checkout.");
+            let merged = merge_into_checkout(&repo, info, their_oid);
+            let mut opts = StashApplyOptions::new();
+            opts.reinstantiate_index();
+            match repo.stash_pop(0, Some(&mut opts)) {
+                Ok(()) => eprintln!("Re-applied the stashed changes."),
+                Err(e) => eprintln!(
+                    "Failed to re-apply the stashed changes: {e}. They are kept in the stash, see `git stash list`."
+                ),
+            }
+            merged
+        }
+    }
+}
+
+/// Merge the session branch into what is checked out, like `git merge`.
+fn merge_into_checkout(repo: &Repository, info: &WorktreeInfo, their_oid: Oid) -> bool {
     let their_commit = match repo.find_annotated_commit(their_oid) {
         Ok(c) => c,
         Err(e) => {
@@ -196,20 +602,21 @@ pub fn merge_worktree(info: &WorktreeInfo) -> bool {
     }
 
     if analysis.is_fast_forward() {
-        // Fast-forward: just move HEAD to the target commit
-        let mut head_ref = match repo.head() {
-            Ok(r) => r,
-            Err(e) => {
-                eprintln!("Failed to get HEAD: {e}");
-                return false;
-            }
-        };
-        if let Err(e) = head_ref.set_target(their_oid, "ajail: fast-forward merge") {
-            eprintln!("Failed to fast-forward: {e}");
-            return false;
-        }
-        if let Err(e) = repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force())) {
-            eprintln!("Failed to checkout after fast-forward: {e}");
+        // Fast-forward: check out the target commit, then move HEAD to it.
+        // The checkout is a safe one from the current HEAD, so it refuses
+        // to overwrite local edits or untracked files instead of losing them.
+        let result = (|| -> Result<(), git2::Error> {
+            let target = repo.find_object(their_oid, None)?;
+            repo.checkout_tree(&target, Some(CheckoutBuilder::new().safe()))?;
+            repo.head()?
+                .set_target(their_oid, "ajail: fast-forward merge")?;
+            Ok(())
+        })();
+        if let Err(e) = result {
+            eprintln!(
+                "Failed to fast-forward: {e}. Worktree preserved at: {}",
+                info.worktree_path.display()
+            );
             return false;
         }
         eprintln!("Merged worktree changes into original branch (fast-forward).");
@@ -269,7 +676,410 @@ pub fn merge_worktree(info: &WorktreeInfo) -> bool {
     }
 }
 
-pub fn cleanup_worktree(info: &WorktreeInfo) {
+/// Merge the session branch into the checked-out branch without touching
+/// the index or the working tree.
+fn merge_without_checkout(repo: &Repository, info: &WorktreeInfo, their_oid: Oid) -> bool {
+    let result = (|| -> Result<bool, git2::Error> {
+        let mut head = repo.head()?;
+        let head_commit = head.peel_to_commit()?;
+        let their_commit = repo.find_commit(their_oid)?;
+        let base = repo.merge_base(head_commit.id(), their_oid)?;
+        if base == their_oid {
+            return Ok(false);
+        }
+        let merged = if base == head_commit.id() {
+            their_oid
+        } else {
+            let mut index = repo.merge_commits(&head_commit, &their_commit, None)?;
+            if index.has_conflicts() {
+                return Err(git2::Error::from_str("the merge has conflicts"));
+            }
+            let tree = repo.find_tree(index.write_tree_to(repo)?)?;
+            let sig = repo.signature()?;
+            let msg = format!("Merge branch '{}'", info.branch_name);
+            repo.commit(
+                None,
+                &sig,
+                &sig,
+                &msg,
+                &tree,
+                &[&head_commit, &their_commit],
+            )?
+        };
+        head.set_target(merged, "ajail: merge without checkout")?;
+        Ok(true)
+    })();
+
+    match result {
+        Ok(false) => {
+            eprintln!("Already up to date.");
+            true
+        }
+        Ok(true) => {
+            eprintln!(
+                "Merged worktree changes into original branch without touching the working tree. Your uncommitted changes are as they were, so `git status` also shows the merged changes as undone."
+            );
+            true
+        }
+        Err(e) => {
+            eprintln!(
+                "Merge failed: {e}. Worktree preserved at: {}",
+                info.worktree_path.display()
+            );
+            false
+        }
+    }
+}
+
+/// Signature for commits ajail makes, falling back to a placeholder when
+/// git has no identity configured.
+fn signature(repo: &Repository) -> Result<Signature<'static>, git2::Error> {
+    repo.signature()
+        .or_else(|_| Signature::now("ajail", "ajail@localhost"))
+}
+
+/// Point the session branch at `oid`, and its worktree too when there is
+/// one, so a merge that fails afterwards leaves a consistent worktree.
+fn move_session_branch(
+    repo: &Repository,
+    info: &WorktreeInfo,
+    oid: Oid,
+) -> Result<(), git2::Error> {
+    let mut branch = repo.find_branch(&info.branch_name, BranchType::Local)?;
+    branch
+        .get_mut()
+        .set_target(oid, "ajail: rewrite session branch")?;
+    if let Ok(worktree) = Repository::open(&info.worktree_path) {
+        let commit = worktree.find_object(oid, None)?;
+        worktree.reset(&commit, ResetType::Hard, None)?;
+    }
+    Ok(())
+}
+
+/// Rebase the session's commits onto the original checkout's HEAD, so
+/// merging them fast-forwards.
+pub fn rebase_worktree(info: &WorktreeInfo) -> bool {
+    let result = (|| -> Result<usize, git2::Error> {
+        let repo = Repository::open(&info.original_repo)?;
+        let head = repo.head()?.peel_to_commit()?.id();
+        let tip = repo
+            .find_branch(&info.branch_name, BranchType::Local)?
+            .get()
+            .peel_to_commit()?
+            .id();
+        if repo.merge_base(head, tip)? == head {
+            return Ok(0);
+        }
+
+        let branch = repo.find_annotated_commit(tip)?;
+        let upstream = repo.find_annotated_commit(head)?;
+        let mut opts = RebaseOptions::new();
+        opts.inmemory(true);
+        let mut rebase = repo.rebase(Some(&branch), Some(&upstream), None, Some(&mut opts))?;
+        let committer = signature(&repo)?;
+        let mut rebased = head;
+        let mut count = 0;
+        while let Some(op) = rebase.next() {
+            let id = op?.id();
+            if rebase.inmemory_index()?.has_conflicts() {
+                rebase.abort()?;
+                let summary = repo.find_commit(id)?.summary().unwrap_or("").to_string();
+                return Err(git2::Error::from_str(&format!(
+                    "conflicts in {} {summary}",
+                    &id.to_string()[..7]
+                )));
+            }
+            match rebase.commit(None, &committer, None) {
+                Ok(oid) => {
+                    rebased = oid;
+                    count += 1;
+                }
+                // Already in the original branch
+                Err(e) if e.code() == ErrorCode::Applied => {}
+                Err(e) => return Err(e),
+            }
+        }
+        rebase.finish(None)?;
+        move_session_branch(&repo, info, rebased)?;
+        Ok(count)
+    })();
+
+    match result {
+        Ok(0) => true,
+        Ok(count) => {
+            eprintln!("Rebased {count} commit(s) onto the original branch.");
+            true
+        }
+        Err(e) => {
+            eprintln!(
+                "Rebase failed: {e}. Worktree preserved at: {}",
+                info.worktree_path.display()
+            );
+            false
+        }
+    }
+}
+
+/// Replace the session's commits by a single one on top of the original
+/// checkout's HEAD, listing them in its message.
+pub fn squash_worktree(info: &WorktreeInfo) -> bool {
+    let session_id = info.branch_name.trim_start_matches("ajail-");
+
+    let result = (|| -> Result<usize, git2::Error> {
+        let repo = Repository::open(&info.original_repo)?;
+        let head = repo.head()?.peel_to_commit()?;
+        let tip = repo
+            .find_branch(&info.branch_name, BranchType::Local)?
+            .get()
+            .peel_to_commit()?;
+
+        let mut revwalk = repo.revwalk()?;
+        revwalk.set_sorting(git2::Sort::REVERSE)?;
+        revwalk.push(tip.id())?;
+        revwalk.hide(head.id())?;
+        let mut summaries = Vec::new();
+        for oid in revwalk {
+            let commit = repo.find_commit(oid?)?;
+            summaries.push(format!("* {}", commit.summary().unwrap_or("")));
+        }
+        if summaries.is_empty() {
+            return Ok(0);
+        }
+
+        // The original branch may have moved on, so squash the result of
+        // merging rather than the branch's own tree
+        let tree = if repo.merge_base(head.id(), tip.id())? == head.id() {
+            tip.tree()?
+        } else {
+            let mut index = repo.merge_commits(&head, &tip, None)?;
+            if index.has_conflicts() {
+                return Err(git2::Error::from_str("the merge has conflicts"));
+            }
+            repo.find_tree(index.write_tree_to(&repo)?)?
+        };
+        let sig = signature(&repo)?;
+        let msg = format!(
+            "ajail: session {session_id}\n\n{}\n\nAjail-Session: {session_id}",
+            summaries.join("\n")
+        );
+        let squashed = repo.commit(None, &sig, &sig, &msg, &tree, &[&head])?;
+        move_session_branch(&repo, info, squashed)?;
+        Ok(summaries.len())
+    })();
+
+    match result {
+        Ok(0) => true,
+        Ok(count) => {
+            eprintln!("Squashed {count} commit(s) into one.");
+            true
+        }
+        Err(e) => {
+            eprintln!(
+                "Squash failed: {e}. Worktree preserved at: {}",
+                info.worktree_path.display()
+            );
+            false
+        }
+    }
+}
+
+/// Remove the worktree but keep its branch, renamed to `name`.
+pub fn keep_branch(info: &WorktreeInfo, name: &str) -> bool {
+    remove_worktree(info);
+
+    let mut kept = info.branch_name.as_str();
+    if name != info.branch_name {
+        let renamed = Repository::open(&info.original_repo).and_then(|repo| {
+            repo.find_branch(&info.branch_name, BranchType::Local)?
+                .rename(name, false)
+                .map(|_| ())
+        });
+        match renamed {
+            Ok(()) => kept = name,
+            Err(e) => eprintln!("Failed to rename the branch to {name}: {e}"),
+        }
+    }
+    eprintln!("Kept the worktree changes on branch {kept}.");
+    true
+}
+
+/// Write the session's commits to `path`: a `git bundle` when it ends in
+/// `.bundle`, a `git format-patch` series in that directory otherwise.
+pub fn write_patches(info: &WorktreeInfo, path: &Path) -> bool {
+    let bundle = path.extension().is_some_and(|ext| ext == "bundle");
+    let path = env::current_dir().map_or_else(|_| path.to_path_buf(), |cwd| cwd.join(path));
+
+    // git2-rs has neither format-patch nor bundles, so shell out
+    let mut cmd = Command::new("git");
+    if bundle {
+        cmd.args(["bundle", "create", "--quiet"])
+            .arg(&path)
+            .arg(&info.branch_name)
+            .arg(format!("^{}", info.original_head));
+    } else {
+        cmd.args(["format-patch", "--quiet", "-o"])
+            .arg(&path)
+            .arg(format!("{}..{}", info.original_head, info.branch_name));
+    }
+    let output = match cmd.current_dir(&info.original_repo).output() {
+        Ok(output) => output,
+        Err(e) => {
+            eprintln!("Failed to run git: {e}");
+            return false;
+        }
+    };
+    if !output.status.success() {
+        eprintln!(
+            "Failed to write {}: {}Worktree preserved at: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr),
+            info.worktree_path.display()
+        );
+        return false;
+    }
+
+    if bundle {
+        eprintln!(
+            "Wrote the worktree changes to {}. Fetch them with `git fetch {} {}`.",
+            path.display(),
+            path.display(),
+            info.branch_name
+        );
+    } else {
+        eprintln!(
+            "Wrote the worktree changes to {}. Apply them with `git am {}/*.patch`.",
+            path.display(),
+            path.display()
+        );
+    }
+    true
+}
+
+/// Private ref the work of a discarded session is kept under.
+fn discarded_ref(session_id: &str) -> String {
+    format!("refs/ajail/discarded/{session_id}")
+}
+
+/// Save the session's work under a private ref before it is discarded, so
+/// `ajail sessions restore` can bring it back. On top of the branch goes a
+/// commit of what was left uncommitted, which also records when the session
+/// was discarded.
+pub fn archive_worktree(info: &WorktreeInfo) -> bool {
+    let session_id = info.branch_name.trim_start_matches("ajail-");
+
+    let result = (|| -> Result<(), git2::Error> {
+        let repo = Repository::open(&info.original_repo)?;
+        let tip = repo
+            .find_branch(&info.branch_name, BranchType::Local)?
+            .get()
+            .peel_to_commit()?;
+        let tree = match Repository::open(&info.worktree_path) {
+            Ok(worktree) => uncommitted_tree(&worktree, info)?,
+            Err(_) => None,
+        };
+        let tree = repo.find_tree(tree.unwrap_or(tip.tree_id()))?;
+        let sig = signature(&repo)?;
+        let msg = format!("ajail: discarded session {session_id}\n\nAjail-Session: {session_id}");
+        let archived = repo.commit(None, &sig, &sig, &msg, &tree, &[&tip])?;
+        repo.reference(&discarded_ref(session_id), archived, true, &msg)?;
+        Ok(())
+    })();
+
+    match result {
+        Ok(()) => {
+            eprintln!(
+                "Saved them as {}, restore with `ajail sessions restore {session_id}`.",
+                discarded_ref(session_id)
+            );
+            true
+        }
+        Err(e) => {
+            eprintln!(
+                "Failed to save the discarded changes: {e}. Worktree preserved at: {}",
+                info.worktree_path.display()
+            );
+            false
+        }
+    }
+}
+
+/// Discarded sessions in `repo_root`, with the Unix time they were
+/// discarded at, oldest first.
+pub fn discarded_sessions(repo_root: &Path) -> Vec<(String, i64)> {
+    let Ok(repo) = Repository::open(repo_root) else {
+        return Vec::new();
+    };
+    let Ok(references) = repo.references_glob(&discarded_ref("*")) else {
+        return Vec::new();
+    };
+    let mut sessions: Vec<(String, i64)> = references
+        .flatten()
+        .filter_map(|reference| {
+            let id = reference.name()?.rsplit('/').next()?.to_string();
+            let discarded = reference
+                .peel_to_commit()
+                .ok()?
+                .committer()
+                .when()
+                .seconds();
+            Some((id, discarded))
+        })
+        .collect();
+    sessions.sort_by_key(|(_, discarded)| *discarded);
+    sessions
+}
+
+/// Delete discarded sessions older than `retention_days`, returning their
+/// ids.
+pub fn expire_discarded(repo_root: &Path, retention_days: u64) -> Vec<String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    let cutoff = now - (retention_days * 86400) as i64;
+    let Ok(repo) = Repository::open(repo_root) else {
+        return Vec::new();
+    };
+    discarded_sessions(repo_root)
+        .into_iter()
+        .filter(|(_, discarded)| *discarded <= cutoff)
+        .filter(|(id, _)| {
+            repo.find_reference(&discarded_ref(id))
+                .and_then(|mut reference| reference.delete())
+                .is_ok()
+        })
+        .map(|(id, _)| id)
+        .collect()
+}
+
+/// Bring a discarded session back as its `ajail-<id>` branch, returning
+/// the branch name.
+pub fn restore_discarded(repo_root: &Path, session_id: &str) -> Result<String, String> {
+    let repo = Repository::open(repo_root).map_err(|e| format!("Failed to open repo: {e}"))?;
+    let mut reference = repo
+        .find_reference(&discarded_ref(session_id))
+        .map_err(|_| {
+            format!(
+                "No discarded session {session_id} in {}",
+                repo_root.display()
+            )
+        })?;
+    let archived = reference
+        .peel_to_commit()
+        .map_err(|e| format!("Failed to read discarded session: {e}"))?;
+    // Without uncommitted changes, the commit on top only marks the time
+    let tip = match archived.parent(0) {
+        Ok(parent) if parent.tree_id() == archived.tree_id() => parent,
+        _ => archived,
+    };
+
+    let branch_name = format!("ajail-{session_id}");
+    repo.branch(&branch_name, &tip, false)
+        .map_err(|e| format!("Failed to create branch {branch_name}: {e}"))?;
+    let _ = reference.delete();
+    Ok(branch_name)
+}
+
+fn remove_worktree(info: &WorktreeInfo) {
     // git2-rs doesn't expose worktree remove, so shell out
     let _ = Command::new("git")
         .args([
@@ -280,6 +1090,16 @@ pub fn cleanup_worktree(info: &WorktreeInfo) {
         ])
         .current_dir(&info.original_repo)
         .output();
+    // A worktree directory removed by other means still blocks deleting
+    // its branch until git forgets about it
+    let _ = Command::new("git")
+        .args(["worktree", "prune"])
+        .current_dir(&info.original_repo)
+        .output();
+}
+
+pub fn cleanup_worktree(info: &WorktreeInfo) {
+    remove_worktree(info);
 
     // Delete the branch using git2
     if let Ok(repo) = Repository::open(&info.original_repo)
@@ -1,5 +1,17 @@
 { pkgs, common }:
 
+let
+  patternsConfig = pkgs.writeText "ajail-config.json" (
+    builtins.toJSON {
+      extraDangerousPaths = [
+        "**/.envrc"
+        "**/.husky/**"
+        ".github/workflows/*"
+      ];
+      ignoreDangerousPaths = [ ".bashrc" ];
+    }
+  );
+in
 {
   deny = pkgs.testers.nixosTest {
     name = "ajail-dangerous-files-deny";
@@ -80,4 +92,115 @@
       machine.succeed("su - testuser -c 'cd ~/projects/myrepo && ajail --allow-dangerous-writes'")
     '';
   };
+
+  patterns = pkgs.testers.nixosTest {
+    name = "ajail-dangerous-files-patterns";
+
+    nodes.machine =
+      { ... }:
+      {
+        imports = [ common.machineConfig ];
+        environment.systemPackages = [
+          (common.mkMockClaude ''
+            # --- Glob patterns from config are protected ---
+            assert_denied "cannot write nested .envrc"      bash -c 'echo x >> sub/dir/.envrc'
+            assert_denied "cannot write root .envrc"        bash -c 'echo x >> .envrc'
+            assert_denied "cannot create in .husky"         touch sub/.husky/new-hook
+            assert_denied "cannot write workflow"           bash -c 'echo x >> .github/workflows/ci.yml'
+            assert_ok     "can read workflow"               cat .github/workflows/ci.yml
+
+            # --- Ignored built-in patterns stay writable ---
+            assert_ok     "can write ignored .bashrc"       bash -c 'echo x >> .bashrc'
+
+            # --- Built-in patterns still apply ---
+            assert_denied "cannot write .git/config"        bash -c 'echo x >> .git/config'
+          '')
+        ];
+      };
+
+    testScript = common.setup + ''
+      machine.succeed("su - testuser -c 'mkdir -p ~/.config/ajail && cp ${patternsConfig} ~/.config/ajail/config.json'")
+
+      machine.succeed("su - testuser -c 'cd ~/projects/myrepo && mkdir -p sub/dir sub/.husky .github/workflows'")
+      machine.succeed("su - testuser -c 'cd ~/projects/myrepo && echo x > sub/dir/.envrc && echo x > .envrc'")
+      machine.succeed("su - testuser -c 'cd ~/projects/myrepo && echo hook > sub/.husky/pre-commit'")
+      machine.succeed("su - testuser -c 'cd ~/projects/myrepo && echo ci > .github/workflows/ci.yml'")
+      machine.succeed("su - testuser -c 'echo bashrc > ~/projects/myrepo/.bashrc'")
+
+      machine.succeed("su - testuser -c 'cd ~/projects/myrepo && ajail'")
+    '';
+  };
+
+  missing-report = pkgs.testers.nixosTest {
+    name = "ajail-dangerous-files-missing-report";
+
+    nodes.machine =
+      { ... }:
+      {
+        imports = [ common.machineConfig ];
+        environment.systemPackages = [
+          (common.mkMockClaude ''
+            # Without --block-dangerous-creation, new dangerous paths can be
+            # created but are reported when the session ends.
+            mkdir -p .vscode
+            echo '{}' > .vscode/tasks.json
+            echo '{}' > .mcp.json
+          '')
+        ];
+      };
+
+    testScript = common.setup + ''
+      output = machine.succeed("su - testuser -c 'cd ~/projects/myrepo && ajail 2>&1'")
+      assert "created dangerous paths" in output, "Expected dangerous path report, got: " + output
+      assert "myrepo/.vscode" in output, "Expected .vscode in report, got: " + output
+      assert "myrepo/.mcp.json" in output, "Expected .mcp.json in report, got: " + output
+    '';
+  };
+
+  missing-block = pkgs.testers.nixosTest {
+    name = "ajail-dangerous-files-missing-block";
+
+    nodes.machine =
+      { ... }:
+      {
+        imports = [ common.machineConfig ];
+        environment.systemPackages = [
+          (common.mkMockClaude ''
+            # --- Missing dangerous paths cannot be created ---
+            assert_denied "cannot create .vscode/tasks.json" bash -c 'echo x > .vscode/tasks.json'
+            assert_denied "cannot create .mcp.json"          bash -c 'echo x > .mcp.json'
+            assert_denied "cannot create .claude/commands"   touch .claude/commands/cmd.md
+            # In a worktree the hooks live in the original repo's git dir
+            assert_denied "cannot create .git/hooks entry" \
+              touch "$(git rev-parse --git-common-dir)/hooks/pre-commit"
+
+            # --- Placeholders are empty directories, invisible to git ---
+            assert_denied "no .mcp.json file to parse"       test -f .mcp.json
+            assert_ok     "placeholders not in git status" \
+              bash -c 'test -z "$(git status --porcelain --untracked-files=all)"'
+
+            # --- Regular files are still writable ---
+            assert_ok "can write regular file" bash -c 'echo test > normal-file'
+            rm -f normal-file
+          '')
+        ];
+      };
+
+    testScript = common.setup + ''
+      machine.succeed("su - testuser -c 'rm -rf ~/projects/myrepo/.git/hooks'")
+
+      machine.succeed("su - testuser -c 'cd ~/projects/myrepo && ajail --block-dangerous-creation'")
+
+      # Placeholders are removed after the session
+      machine.fail("su - testuser -c 'test -e ~/projects/myrepo/.vscode'")
+      machine.fail("su - testuser -c 'test -e ~/projects/myrepo/.mcp.json'")
+      machine.fail("su - testuser -c 'test -e ~/projects/myrepo/.claude'")
+      machine.fail("su - testuser -c 'test -e ~/projects/myrepo/.git/hooks'")
+
+      # In a worktree, .git is a file and .git/ patterns apply to the
+      # original repo's git dir
+      machine.succeed("su - testuser -c 'cd ~/projects/myrepo && ajail --worktree --worktree-action discard --block-dangerous-creation'")
+      machine.fail("su - testuser -c 'test -e ~/projects/myrepo/.git/hooks'")
+    '';
+  };
 }
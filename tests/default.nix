@@ -9,6 +9,14 @@ in
   config-dir = import ./config-dir.nix args;
   worktree-merge = import ./worktree-merge.nix args;
   worktree-discard = import ./worktree-discard.nix args;
+  worktree-carry = import ./worktree-carry.nix args;
+  worktree-uncommitted = import ./worktree-uncommitted.nix args;
+  worktree-dirty = import ./worktree-dirty.nix args;
+  worktree-actions = import ./worktree-actions.nix args;
+  worktree-restore = import ./worktree-restore.nix args;
+  overlay-merge = (import ./overlay.nix args).merge;
+  overlay-discard = (import ./overlay.nix args).discard;
+  overlay-conflict = (import ./overlay.nix args).conflict;
   ssh-agent-allow = (import ./ssh-agent.nix args).allow;
   ssh-agent-deny = (import ./ssh-agent.nix args).deny;
   claude-binary = import ./claude-binary.nix args;
@@ -21,9 +29,31 @@ in
   path-readonly = import ./path-readonly.nix args;
   dangerous-files-deny = (import ./dangerous-files.nix args).deny;
   dangerous-files-allow = (import ./dangerous-files.nix args).allow;
+  dangerous-files-patterns = (import ./dangerous-files.nix args).patterns;
+  dangerous-files-missing-report = (import ./dangerous-files.nix args).missing-report;
+  dangerous-files-missing-block = (import ./dangerous-files.nix args).missing-block;
   pid-namespace = import ./pid-namespace.nix args;
   unix-sockets-allow = (import ./unix-sockets.nix args).allow;
   unix-sockets-deny = (import ./unix-sockets.nix args).deny;
+  unix-sockets-compat-deny = (import ./unix-sockets-compat.nix args).deny;
+  unix-sockets-compat-allow = (import ./unix-sockets-compat.nix args).allow;
   unix-sockets-aarch64-allow = (import ./unix-sockets-aarch64.nix args).allow;
   unix-sockets-aarch64-deny = (import ./unix-sockets-aarch64.nix args).deny;
+  network-host = (import ./network.nix args).host;
+  network-none = (import ./network.nix args).none;
+  network-proxy = (import ./network.nix args).proxy;
+  user-mounts = import ./user-mounts.nix args;
+  env-policy-deny = (import ./env-policy.nix args).deny;
+  env-policy-clean = (import ./env-policy.nix args).clean;
+  landlock = import ./landlock.nix args;
+  seccomp-rules = import ./seccomp-rules.nix args;
+  seccomp-profile = import ./seccomp-profile.nix args;
+  seccomp-notify = import ./seccomp-notify.nix args;
+  terminal-injection = import ./terminal-injection.nix args;
+  pty = import ./pty.nix args;
+  record = import ./record.nix args;
+  session-lifecycle = import ./session-lifecycle.nix args;
+  sessions = import ./sessions.nix args;
+  seccomp-hardening-deny = (import ./seccomp-hardening.nix args).deny;
+  seccomp-hardening-disabled = (import ./seccomp-hardening.nix args).disabled;
 }
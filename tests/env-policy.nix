@@ -0,0 +1,63 @@
+{ pkgs, common }:
+
+let
+  secrets = "GITHUB_TOKEN=ghp_x AWS_SECRET_ACCESS_KEY=aws_x OPENAI_API_KEY=oa_x STRIPE_SECRETS=st_x MY_DB_PASSWORD=db_x ANTHROPIC_API_KEY=sk_x HARMLESS=ok";
+in
+{
+  deny = pkgs.testers.nixosTest {
+    name = "ajail-env-policy-deny";
+
+    nodes.machine =
+      { ... }:
+      {
+        imports = [ common.machineConfig ];
+        environment.systemPackages = [
+          (common.mkMockClaude ''
+            # --- Default denylist strips common secrets ---
+            assert_ok "GITHUB_TOKEN stripped"          test -z "$GITHUB_TOKEN"
+            assert_ok "AWS_SECRET_ACCESS_KEY stripped" test -z "$AWS_SECRET_ACCESS_KEY"
+            assert_ok "OPENAI_API_KEY stripped"        test -z "$OPENAI_API_KEY"
+            assert_ok "STRIPE_SECRETS stripped"        test -z "$STRIPE_SECRETS"
+
+            # --- Explicitly forwarded and agent credentials are kept ---
+            assert_ok "MY_DB_PASSWORD forwarded via --env" test "$MY_DB_PASSWORD" = db_x
+            assert_ok "ANTHROPIC_API_KEY kept"             test "$ANTHROPIC_API_KEY" = sk_x
+
+            # --- Other variables pass through, overrides are set ---
+            assert_ok "HARMLESS forwarded"     test "$HARMLESS" = ok
+            assert_ok "EXTRA set via --env"    test "$EXTRA" = value
+          '')
+        ];
+      };
+
+    testScript = common.setup + ''
+      output = machine.succeed("su - testuser -c 'cd ~/projects/myrepo && env ${secrets} ajail --env MY_DB_PASSWORD --env EXTRA=value 2>&1'")
+      assert "GITHUB_TOKEN" in output, "Expected GITHUB_TOKEN in stripped report, got: " + output
+      assert "AWS_SECRET_ACCESS_KEY" in output, "Expected AWS_SECRET_ACCESS_KEY in stripped report, got: " + output
+    '';
+  };
+
+  clean = pkgs.testers.nixosTest {
+    name = "ajail-env-policy-clean";
+
+    nodes.machine =
+      { ... }:
+      {
+        imports = [ common.machineConfig ];
+        environment.systemPackages = [
+          (common.mkMockClaude ''
+            # --- Only the base environment and --env names are forwarded ---
+            assert_ok "HARMLESS stripped"       test -z "$HARMLESS"
+            assert_ok "GITHUB_TOKEN stripped"   test -z "$GITHUB_TOKEN"
+            assert_ok "PATH kept"               test -n "$PATH"
+            assert_ok "HOME kept"               test "$HOME" = /home/testuser
+            assert_ok "MY_DB_PASSWORD forwarded via --env" test "$MY_DB_PASSWORD" = db_x
+          '')
+        ];
+      };
+
+    testScript = common.setup + ''
+      machine.succeed("su - testuser -c 'cd ~/projects/myrepo && env ${secrets} ajail --clean-env --env MY_DB_PASSWORD'")
+    '';
+  };
+}
@@ -0,0 +1,34 @@
+{ pkgs, common }:
+
+pkgs.testers.nixosTest {
+  name = "ajail-landlock";
+
+  nodes.machine =
+    { ... }:
+    {
+      imports = [ common.machineConfig ];
+      environment.systemPackages = [
+        (common.mkMockClaude ''
+          # --- Allowed write locations ---
+          assert_ok "can write repo"          bash -c 'echo x > landlock-test && rm landlock-test'
+          assert_ok "can write /tmp"          bash -c 'echo x > /tmp/landlock-test'
+          assert_ok "can write claude config" bash -c 'echo x > ~/.claude/landlock-test'
+          assert_ok "can write --tmpfs mount" bash -c 'echo x > ~/scratch/landlock-test'
+          assert_ok "can write /dev/null"     bash -c 'echo x > /dev/null'
+
+          # --- The tmpfs home is writable, so tools can keep caches there ---
+          assert_ok "can write home"          bash -c 'echo x > ~/landlock-test'
+          assert_ok "can create home dirs"    mkdir -p ~/.cache/tool
+
+          # --- Reads and exec still work everywhere visible ---
+          assert_ok "can read /etc" cat /etc/hostname
+          assert_ok "can exec"      ls /
+        '')
+      ];
+    };
+
+  testScript = common.setup + ''
+    output = machine.succeed("su - testuser -c 'cd ~/projects/myrepo && ajail --landlock --tmpfs ~/scratch 2>&1'")
+    assert "Landlock ABI v" in output, "Expected Landlock ABI report, got: " + output
+  '';
+}
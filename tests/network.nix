@@ -0,0 +1,137 @@
+{ pkgs, common }:
+
+let
+  # Connect to a TCP port on 127.0.0.1, exit non-zero on failure
+  connect = port: ''
+    python3 -c "import socket; socket.create_connection(('127.0.0.1', ${toString port}), timeout=2).close()"
+  '';
+
+  # Listen on loopback and connect to ourselves
+  loopbackRoundtrip = ''
+    python3 -c "import socket; s = socket.socket(); s.bind(('127.0.0.1', 0)); s.listen(1); socket.create_connection(s.getsockname(), timeout=2).close()"
+  '';
+
+  startHostListener = ''
+    machine.succeed("systemd-run --unit=host-listener ${pkgs.python3}/bin/python3 -m http.server 8765 --bind 127.0.0.1")
+    machine.wait_for_open_port(8765)
+  '';
+
+  # Send raw bytes to the HTTP proxy and print the status lines of the reply
+  proxyRaw = pkgs.writeScript "proxy-raw" ''
+    #!${pkgs.python3}/bin/python3
+    import os, socket, sys
+    host, port = os.environ["HTTP_PROXY"].removeprefix("http://").rsplit(":", 1)
+    s = socket.create_connection((host, int(port)), timeout=5)
+    s.sendall(sys.argv[1].encode().decode("unicode_escape").encode())
+    reply = b""
+    while chunk := s.recv(4096):
+        reply += chunk
+    print("\n".join(l.decode() for l in reply.split(b"\r\n") if l.startswith(b"HTTP/")))
+  '';
+in
+{
+  host = pkgs.testers.nixosTest {
+    name = "ajail-network-host";
+
+    nodes.machine =
+      { ... }:
+      {
+        imports = [ common.machineConfig ];
+        environment.systemPackages = [
+          pkgs.python3
+          (common.mkMockClaude ''
+            # --- Host network stack is shared by default ---
+            assert_ok "host listener reachable" ${connect 8765}
+            assert_ok "loopback works" ${loopbackRoundtrip}
+          '')
+        ];
+      };
+
+    testScript = common.setup + startHostListener + ''
+      machine.succeed("su - testuser -c 'cd ~/projects/myrepo && ajail'")
+    '';
+  };
+
+  none = pkgs.testers.nixosTest {
+    name = "ajail-network-none";
+
+    nodes.machine =
+      { ... }:
+      {
+        imports = [ common.machineConfig ];
+        environment.systemPackages = [
+          pkgs.python3
+          (common.mkMockClaude ''
+            # --- Host network stack is not reachable ---
+            assert_denied "host listener unreachable" ${connect 8765}
+
+            # --- Loopback is up inside the fresh namespace ---
+            assert_ok "loopback works" ${loopbackRoundtrip}
+
+            # --- Only the loopback interface exists ---
+            IFACES="$(python3 -c "import socket; print(' '.join(n for _, n in socket.if_nameindex()))")"
+            if [ "$IFACES" = "lo" ]; then
+              echo "OK: only lo present"
+            else
+              echo "FAIL: unexpected interfaces: $IFACES"
+              FAIL=1
+            fi
+          '')
+        ];
+      };
+
+    testScript = common.setup + startHostListener + ''
+      machine.succeed("su - testuser -c 'cd ~/projects/myrepo && ajail --network none'")
+    '';
+  };
+
+  proxy = pkgs.testers.nixosTest {
+    name = "ajail-network-proxy";
+
+    nodes.machine =
+      { ... }:
+      {
+        imports = [ common.machineConfig ];
+        environment.systemPackages = [
+          pkgs.python3
+          pkgs.curl
+          (common.mkMockClaude ''
+            # --- Proxy variables are injected ---
+            assert_ok "HTTPS_PROXY set" test -n "$HTTPS_PROXY"
+            assert_ok "HTTP_PROXY set"  test -n "$HTTP_PROXY"
+
+            # --- Direct connections cannot leave the sandbox ---
+            assert_denied "host listener unreachable directly" ${connect 8765}
+
+            # --- Allowlisted hosts are reachable through the proxy ---
+            assert_ok "allowed host via HTTP proxy" \
+              curl -sf --noproxy "" -x "$HTTP_PROXY" http://localhost:8765/
+            assert_ok "allowed host via CONNECT" \
+              curl -sf -p --noproxy "" -x "$HTTPS_PROXY" http://localhost:8765/
+
+            # --- Other hosts are refused ---
+            assert_denied "denied host via HTTP proxy" \
+              curl -sf --noproxy "" -x "$HTTP_PROXY" http://not-allowed.test:8765/
+            assert_denied "denied host via CONNECT" \
+              curl -sf -p --noproxy "" -x "$HTTPS_PROXY" http://not-allowed.test:8765/
+
+            # --- Plain HTTP connections carry a single request ---
+            STATUS="$(${proxyRaw} 'GET http://localhost:8765/ HTTP/1.1\r\n\r\nGET http://not-allowed.test:8765/ HTTP/1.1\r\n\r\n')"
+            if [ "$(echo "$STATUS" | wc -l)" = 1 ] && echo "$STATUS" | grep -q " 200 "; then
+              echo "OK: pipelined request not forwarded"
+            else
+              echo "FAIL: unexpected replies to pipelined requests: $STATUS"
+              FAIL=1
+            fi
+            assert_ok "chunked upload refused" \
+              sh -c "${proxyRaw} 'POST http://localhost:8765/ HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n' | grep -q ' 411 '"
+          '')
+        ];
+      };
+
+    testScript = common.setup + startHostListener + ''
+      output = machine.succeed("su - testuser -c 'cd ~/projects/myrepo && ajail --network proxy --proxy-allow localhost 2>&1'")
+      assert "not-allowed.test:8765" in output, "Expected denied host in proxy report, got: " + output
+    '';
+  };
+}
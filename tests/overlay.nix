@@ -0,0 +1,99 @@
+{ pkgs, common }:
+
+let
+  # Mock claude that edits, creates and deletes files in the project
+  mockClaude = common.mkMockClaude ''
+    assert_ok "can modify tracked file" bash -c 'echo changed > existing-file'
+    assert_ok "can create new file"     bash -c 'echo new > new-file'
+    assert_ok "can delete file"         rm untracked-file
+    assert_not_exists "deleted file gone inside sandbox" untracked-file
+  '';
+
+  # The common setup repo has no commits, which worktree mode cannot handle
+  setup = common.setup + ''
+    machine.succeed("su - testuser -c 'echo untracked > ~/projects/myrepo/untracked-file'")
+  '';
+in
+{
+  merge = pkgs.testers.nixosTest {
+    name = "ajail-overlay-merge";
+
+    nodes.machine =
+      { ... }:
+      {
+        imports = [ common.machineConfig ];
+        environment.systemPackages = [ mockClaude ];
+      };
+
+    testScript = setup + ''
+      machine.succeed("su - testuser -c 'cd ~/projects/myrepo && ajail --overlay --worktree-action merge'")
+
+      # Changes are applied to the real tree
+      machine.succeed("su - testuser -c 'grep changed ~/projects/myrepo/existing-file'")
+      machine.succeed("su - testuser -c 'grep new ~/projects/myrepo/new-file'")
+      machine.fail("su - testuser -c 'test -e ~/projects/myrepo/untracked-file'")
+
+      # No leftover session directories
+      machine.fail("ls -d /tmp/ajail-overlay-*")
+    '';
+  };
+
+  discard = pkgs.testers.nixosTest {
+    name = "ajail-overlay-discard";
+
+    nodes.machine =
+      { ... }:
+      {
+        imports = [ common.machineConfig ];
+        environment.systemPackages = [ mockClaude ];
+      };
+
+    testScript = setup + ''
+      machine.succeed("su - testuser -c 'cd ~/projects/myrepo && ajail --overlay --worktree-action discard'")
+
+      # The real tree is untouched
+      machine.succeed("su - testuser -c 'grep existing ~/projects/myrepo/existing-file'")
+      machine.fail("su - testuser -c 'test -e ~/projects/myrepo/new-file'")
+      machine.succeed("su - testuser -c 'grep untracked ~/projects/myrepo/untracked-file'")
+
+      # No leftover session directories
+      machine.fail("ls -d /tmp/ajail-overlay-*")
+    '';
+  };
+
+  conflict = pkgs.testers.nixosTest {
+    name = "ajail-overlay-conflict";
+
+    nodes.machine =
+      { ... }:
+      {
+        imports = [ common.machineConfig ];
+        environment.systemPackages = [
+          (common.mkMockClaude ''
+            echo changed > existing-file
+            echo edited
+            # Give the host time to edit the same file
+            sleep 5
+          '')
+        ];
+      };
+
+    testScript = setup + ''
+      machine.succeed(
+        "su - testuser -c 'cd ~/projects/myrepo && "
+        + "{ ajail --overlay --worktree-action merge < /dev/null > /tmp/conflict.log 2>&1; echo exit $? >> /tmp/conflict.log; } &'"
+      )
+      machine.wait_until_succeeds("grep -q edited /tmp/conflict.log")
+      machine.succeed("su - testuser -c 'echo host > ~/projects/myrepo/existing-file'")
+      machine.wait_until_succeeds("grep -q '^exit' /tmp/conflict.log")
+
+      # Without a terminal to confirm, the host's edit wins and the upper
+      # layer is kept
+      output = machine.succeed("cat /tmp/conflict.log")
+      assert "changed on the host during the session" in output, "Expected conflict report, got: " + output
+      assert "Not applying overlay changes" in output, "Expected the merge to be refused, got: " + output
+      machine.succeed("su - testuser -c 'grep host ~/projects/myrepo/existing-file'")
+      machine.succeed("ls -d /tmp/ajail-overlay-*")
+    '';
+  };
+}
@@ -1,5 +1,13 @@
 { pkgs, common }:
 
+let
+  trapTerm = pkgs.writeShellScript "trap-term" ''
+    trap 'echo got TERM; exit 7' TERM
+    echo ready
+    sleep 60 &
+    wait
+  '';
+in
 pkgs.testers.nixosTest {
   name = "ajail-pid-namespace";
 
@@ -9,12 +17,35 @@ pkgs.testers.nixosTest {
       imports = [ common.machineConfig ];
       environment.systemPackages = [
         (common.mkMockClaude ''
-          # --- Running as PID 1 in new PID namespace ---
+          # --- Running under ajail's init in a new PID namespace ---
           MY_PID=$$
-          if [ "$MY_PID" -eq 1 ]; then
-            echo "OK: running as PID 1"
+          if [ "$MY_PID" -eq 2 ]; then
+            echo "OK: running as PID 2"
+          else
+            echo "FAIL: expected PID 2, got $MY_PID"
+            FAIL=1
+          fi
+
+          INIT="$(cat /proc/1/comm)"
+          if [ "$INIT" = "ajail" ]; then
+            echo "OK: PID 1 is ajail's init"
+          else
+            echo "FAIL: expected ajail as PID 1, got $INIT"
+            FAIL=1
+          fi
+
+          # --- PID 1 is locked down against the command ---
+          if head -c 1 /proc/1/mem >/dev/null 2>&1; then
+            echo "FAIL: PID 1 memory readable through /proc/1/mem"
+            FAIL=1
           else
-            echo "FAIL: expected PID 1, got $MY_PID"
+            echo "OK: PID 1 memory not readable"
+          fi
+
+          if grep -q '^Seccomp:[[:space:]]*2' /proc/1/status; then
+            echo "OK: PID 1 runs under a seccomp filter"
+          else
+            echo "FAIL: PID 1 has no seccomp filter"
             FAIL=1
           fi
 
@@ -51,6 +82,17 @@ pkgs.testers.nixosTest {
             echo "FAIL: PID namespace is the same as host ($SANDBOX_PIDNS)"
             FAIL=1
           fi
+
+          # --- Orphaned processes are reaped by PID 1 ---
+          sh -c 'sleep 0.2 & exit 0'
+          sleep 1
+          ZOMBIES=$(grep -l '^State:.*Z' /proc/[0-9]*/status 2>/dev/null | wc -l)
+          if [ "$ZOMBIES" -eq 0 ]; then
+            echo "OK: orphans reaped"
+          else
+            echo "FAIL: $ZOMBIES zombie processes left"
+            FAIL=1
+          fi
         '')
       ];
     };
@@ -60,5 +102,16 @@ pkgs.testers.nixosTest {
     machine.succeed("su - testuser -c 'readlink /proc/self/ns/pid > ~/projects/myrepo/.host_pidns'")
 
     machine.succeed("su - testuser -c 'cd ~/projects/myrepo && ajail'")
+
+    # SIGTERM sent to the sandbox's PID 1 reaches the command, and its
+    # exit status comes back out
+    machine.succeed(
+      "su - testuser -c 'cd ~/projects/myrepo && "
+      + "{ ajail --command ${trapTerm} > /tmp/signal.log 2>&1; echo exit $? >> /tmp/signal.log; } &'"
+    )
+    machine.wait_until_succeeds("grep -q ready /tmp/signal.log")
+    machine.succeed("pkill -TERM -n -x ajail")
+    machine.wait_until_succeeds("grep -q '^exit 7$' /tmp/signal.log")
+    machine.succeed("grep -q 'got TERM' /tmp/signal.log")
   '';
 }
@@ -0,0 +1,43 @@
+{ pkgs, common }:
+
+pkgs.testers.nixosTest {
+  name = "ajail-pty";
+
+  nodes.machine =
+    { ... }:
+    {
+      imports = [ common.machineConfig ];
+      environment.systemPackages = [
+        pkgs.util-linux
+        (common.mkMockClaude ''
+          # --- The command runs on a terminal of its own ---
+          assert_ok "stdin is a terminal"  test -t 0
+          assert_ok "stdout is a terminal" test -t 1
+          echo "sandbox tty: $(tty)"
+
+          # --- The host terminal size is passed on ---
+          assert_ok "window size copied" sh -c '[ "$(stty size)" = "30 100" ]'
+
+          # --- Input is relayed from the host terminal ---
+          read -r line
+          assert_ok "input relayed" test "$line" = "hello"
+        '')
+      ];
+    };
+
+  testScript = common.setup + ''
+    # Run ajail on a terminal, record the host tty and its modes around it
+    output = machine.succeed(
+      "su - testuser -c \"cd ~/projects/myrepo && echo hello | "
+      + "script -qec 'stty rows 30 cols 100; echo host tty: \\$(tty); stty -g > /tmp/before; ajail; stty -g > /tmp/after' /dev/null\""
+    )
+    print(output)
+    host_tty = output.split("host tty: ")[1].split()[0]
+    sandbox_tty = output.split("sandbox tty: ")[1].split()[0]
+    assert host_tty != sandbox_tty, "Expected a separate pty, got " + sandbox_tty + " for both"
+    assert "FAIL" not in output, "Mock reported a failure: " + output
+
+    # Terminal modes are restored after the session
+    machine.succeed("cmp /tmp/before /tmp/after")
+  '';
+}
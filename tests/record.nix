@@ -0,0 +1,50 @@
+{ pkgs, common }:
+
+pkgs.testers.nixosTest {
+  name = "ajail-record";
+
+  nodes.machine =
+    { ... }:
+    {
+      imports = [ common.machineConfig ];
+      environment.systemPackages = [
+        pkgs.util-linux
+        pkgs.jq
+        (common.mkMockClaude ''
+          echo "recorded marker"
+        '')
+      ];
+    };
+
+  testScript = common.setup + ''
+    def on_terminal(args):
+        return machine.succeed(
+            "su - testuser -c \"cd ~/projects/myrepo && "
+            + "script -qec 'stty rows 30 cols 100; ajail " + args + "' /dev/null\""
+        )
+
+    # --- --record writes an asciicast v2 file ---
+    output = on_terminal("--record /tmp/session.cast")
+    print(output)
+    assert "session recorded to /tmp/session.cast" in output, "Expected the recording path, got: " + output
+    header = "head -n1 /tmp/session.cast | jq -e"
+    machine.succeed(header + " '.version == 2 and .width == 100 and .height == 30'")
+    machine.succeed(header + " '.ajail.sessionId | length > 0'")
+    machine.succeed(header + " '.ajail.options.network == \"host\"'")
+    machine.succeed(header + " '.ajail.options | has(\"env\") | not'")
+    machine.succeed("tail -n +2 /tmp/session.cast | jq -e 'select(.[1] == \"o\") | .[2]' | grep -q 'recorded marker'")
+    assert machine.succeed("stat -c %a /tmp/session.cast").strip() == "600"
+
+    # --- Without a terminal, nothing is recorded ---
+    machine.succeed("rm /tmp/session.cast")
+    output = machine.succeed("su - testuser -c 'cd ~/projects/myrepo && ajail --record /tmp/session.cast < /dev/null 2>&1'")
+    assert "session not recorded" in output, "Expected a warning, got: " + output
+    machine.fail("test -e /tmp/session.cast")
+
+    # --- recordSessions records every session under the state directory ---
+    machine.succeed("su - testuser -c 'mkdir -p ~/.config/ajail && echo {\\\"recordSessions\\\": true} > ~/.config/ajail/config.json'")
+    output = on_terminal("")
+    assert "/.local/state/ajail/sessions/" in output, "Expected a session recording, got: " + output
+    machine.succeed("su - testuser -c 'ls ~/.local/state/ajail/sessions/*.cast'")
+  '';
+}
@@ -0,0 +1,75 @@
+{ pkgs, common }:
+
+let
+  # Run a raw syscall (x86_64 numbers), exit non-zero if it fails
+  syscall = nr: args: ''
+    python3 -c "import ctypes, sys; sys.exit(ctypes.CDLL(None).syscall(${toString nr}, ${args}) < 0)"
+  '';
+
+  keyctl = syscall 250 "0, -3, 0"; # keyctl(KEYCTL_GET_KEYRING_ID, KEY_SPEC_USER_KEYRING, 0)
+  addKey = syscall 248 "b'user', b'ajail', b'v', 1, -3"; # add_key(..., KEY_SPEC_USER_KEYRING)
+  userfaultfd = syscall 323 "1"; # userfaultfd(UFFD_USER_MODE_ONLY)
+
+  # Fork a child and PTRACE_ATTACH to it
+  ptraceAttach = ''
+    python3 -c "import ctypes, os, sys, time; pid = os.fork() or (time.sleep(5), os._exit(0)); r = ctypes.CDLL(None).syscall(101, 16, pid, 0, 0); os.kill(pid, 9); sys.exit(r < 0)"
+  '';
+
+  # Fork a child that asks to be traced with PTRACE_TRACEME
+  ptraceTraceme = ''
+    python3 -c "import ctypes, os, sys; pid = os.fork() or os._exit(ctypes.CDLL(None).syscall(101, 0, 0, 0, 0) < 0); sys.exit(os.waitstatus_to_exitcode(os.waitpid(pid, 0)[1]))"
+  '';
+in
+{
+  deny = pkgs.testers.nixosTest {
+    name = "ajail-seccomp-hardening-deny";
+
+    nodes.machine =
+      { ... }:
+      {
+        imports = [ common.machineConfig ];
+        environment.systemPackages = [
+          pkgs.python3
+          (common.mkMockClaude ''
+            # --- Kernel attack surface is denied by default ---
+            assert_denied "keyctl denied"            ${keyctl}
+            assert_denied "add_key denied"           ${addKey}
+            assert_denied "userfaultfd denied"       ${userfaultfd}
+
+            # --- Attaching to processes is denied, being traced is not ---
+            assert_denied "ptrace attach denied"     ${ptraceAttach}
+            assert_ok     "ptrace traceme allowed"   ${ptraceTraceme}
+          '')
+        ];
+      };
+
+    testScript = common.setup + ''
+      # The hardening profile applies even when Unix sockets are allowed
+      machine.succeed("su - testuser -c 'cd ~/projects/myrepo && ajail --allow-unix-sockets'")
+    '';
+  };
+
+  disabled = pkgs.testers.nixosTest {
+    name = "ajail-seccomp-hardening-disabled";
+
+    nodes.machine =
+      { ... }:
+      {
+        imports = [ common.machineConfig ];
+        environment.systemPackages = [
+          pkgs.python3
+          (common.mkMockClaude ''
+            # --- The opt-out restores the syscalls ---
+            assert_ok "keyctl allowed"        ${keyctl}
+            assert_ok "add_key allowed"       ${addKey}
+            assert_ok "userfaultfd allowed"   ${userfaultfd}
+            assert_ok "ptrace attach allowed" ${ptraceAttach}
+          '')
+        ];
+      };
+
+    testScript = common.setup + ''
+      machine.succeed("su - testuser -c 'cd ~/projects/myrepo && ajail --disable-seccomp-hardening'")
+    '';
+  };
+}
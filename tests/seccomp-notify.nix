@@ -0,0 +1,90 @@
+{ pkgs, common }:
+
+let
+  repo = "/home/testuser/projects/myrepo";
+
+  # Unix socket servers on the host answering "ping" with "pong"
+  socketServer = pkgs.writeText "socket-server.py" ''
+    import os, socket, sys, threading
+
+    def serve(path):
+        if os.path.exists(path):
+            os.unlink(path)
+        server = socket.socket(socket.AF_UNIX)
+        server.bind(path)
+        server.listen()
+        while True:
+            conn, _ = server.accept()
+            if conn.recv(4) == b"ping":
+                conn.sendall(b"pong")
+            conn.close()
+
+    for path in sys.argv[1:]:
+        threading.Thread(target=serve, args=(path,), daemon=True).start()
+    threading.Event().wait()
+  '';
+
+  # Connect to a Unix socket and expect "pong"
+  ping = path: ''
+    python3 -c "import socket; s = socket.socket(socket.AF_UNIX); s.connect('${path}'); s.sendall(b'ping'); assert s.recv(4) == b'pong'"
+  '';
+
+  config = pkgs.writeText "ajail-config.json" (
+    builtins.toJSON {
+      unixSocketAllow = [
+        "${repo}/allowed.sock"
+        "${repo}/link.sock"
+        "${repo}/linkdir/*"
+      ];
+    }
+  );
+in
+pkgs.testers.nixosTest {
+  name = "ajail-seccomp-notify";
+
+  nodes.machine =
+    { ... }:
+    {
+      imports = [ common.machineConfig ];
+      environment.systemPackages = [
+        pkgs.python3
+        (common.mkMockClaude ''
+          # --- Creating Unix sockets succeeds, connecting is brokered ---
+          assert_ok     "unix socket creation allowed" \
+            python3 -c "import socket; s = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM); s.close()"
+          assert_ok     "allowlisted socket reachable"      ${ping "${repo}/allowed.sock"}
+          assert_denied "other socket denied without a tty" ${ping "${repo}/denied.sock"}
+          assert_ok     "dot components cleaned up"         ${ping "${repo}/./sub/../allowed.sock"}
+
+          # --- Allowed names cannot be symlinks to other host sockets ---
+          assert_denied "symlinked socket refused"          ${ping "${repo}/link.sock"}
+          assert_denied "socket under symlinked dir refused" ${ping "${repo}/linkdir/host.sock"}
+
+          # --- Other connections are not affected ---
+          assert_ok     "tcp connect allowed" \
+            python3 -c "import socket; l = socket.create_server(('127.0.0.1', 0)); socket.create_connection(l.getsockname())"
+        '')
+      ];
+    };
+
+  testScript = common.setup + ''
+    machine.succeed("systemd-run --uid=testuser --unit=socket-server ${pkgs.python3}/bin/python3 ${socketServer} ${repo}/allowed.sock ${repo}/denied.sock")
+    machine.wait_for_file("${repo}/denied.sock")
+
+    # A host socket outside the allowlist, reachable through symlinks in the repo
+    machine.succeed("install -d -o testuser /run/ajail-host")
+    machine.succeed("systemd-run --uid=testuser --unit=host-socket-server ${pkgs.python3}/bin/python3 ${socketServer} /run/ajail-host/host.sock")
+    machine.wait_for_file("/run/ajail-host/host.sock")
+    machine.succeed("su - testuser -c 'mkdir ${repo}/sub && ln -s /run/ajail-host/host.sock ${repo}/link.sock && ln -s /run/ajail-host ${repo}/linkdir'")
+
+    machine.succeed("su - testuser -c 'mkdir -p ~/.config/ajail && cp ${config} ~/.config/ajail/config.json'")
+    output = machine.succeed("su - testuser -c 'cd ~/projects/myrepo && ajail 2>&1'")
+    assert "${repo}/allowed.sock (allowed 2x)" in output, "Expected allowed socket in report, got: " + output
+    assert "${repo}/denied.sock (denied 1x)" in output, "Expected denied socket in report, got: " + output
+
+    # Without the broker, Unix sockets are still blocked outright
+    machine.succeed("su - testuser -c 'rm ~/.config/ajail/config.json'")
+    output = machine.succeed("su - testuser -c 'cd ~/projects/myrepo && ajail --command ${pkgs.writeShellScript "ping" (ping "${repo}/allowed.sock")} 2>&1 || echo refused'")
+    assert "refused" in output, "Expected Unix socket block without broker, got: " + output
+  '';
+}
@@ -0,0 +1,139 @@
+{ pkgs, common }:
+
+let
+  # Docker-style profile: allow by default, deny directory creation and
+  # uname-26 personality changes
+  profile = pkgs.writeText "seccomp-profile.json" (
+    builtins.toJSON {
+      defaultAction = "SCMP_ACT_ALLOW";
+      archMap = [
+        {
+          architecture = "SCMP_ARCH_X86_64";
+          subArchitectures = [
+            "SCMP_ARCH_X86"
+            "SCMP_ARCH_X32"
+          ];
+        }
+      ];
+      syscalls = [
+        {
+          names = [
+            "mkdir"
+            "mkdirat"
+          ];
+          action = "SCMP_ACT_ERRNO";
+          errnoRet = 13;
+        }
+        {
+          names = [ "personality" ];
+          action = "SCMP_ACT_ERRNO";
+          args = [
+            {
+              index = 0;
+              value = 131072; # UNAME26
+              valueTwo = 131072;
+              op = "SCMP_CMP_MASKED_EQ";
+            }
+          ];
+        }
+        {
+          # Requires a capability the sandbox does not have, so it is skipped
+          names = [ "getpid" ];
+          action = "SCMP_ACT_KILL";
+          includes = {
+            caps = [ "CAP_SYS_ADMIN" ];
+          };
+        }
+      ];
+    }
+  );
+
+  config = pkgs.writeText "ajail-config.json" (builtins.toJSON { seccompProfile = "${profile}"; });
+
+  unsupportedProfile = pkgs.writeText "seccomp-profile.json" (
+    builtins.toJSON {
+      defaultAction = "SCMP_ACT_ALLOW";
+      syscalls = [
+        {
+          names = [ "ptrace" ];
+          action = "SCMP_ACT_TRACE";
+        }
+      ];
+    }
+  );
+
+  unknownArchProfile = pkgs.writeText "seccomp-profile.json" (
+    builtins.toJSON {
+      defaultAction = "SCMP_ACT_ALLOW";
+      architectures = [ "SCMP_ARCH_X86_46" ];
+    }
+  );
+
+  # First match and libseccomp's most specific match would disagree here
+  overlappingProfile = pkgs.writeText "seccomp-profile.json" (
+    builtins.toJSON {
+      defaultAction = "SCMP_ACT_ALLOW";
+      syscalls = [
+        {
+          names = [ "mkdir" ];
+          action = "SCMP_ACT_ERRNO";
+        }
+        {
+          names = [ "mkdir" ];
+          action = "SCMP_ACT_ALLOW";
+          args = [
+            {
+              index = 1;
+              value = 448;
+              op = "SCMP_CMP_EQ";
+            }
+          ];
+        }
+      ];
+    }
+  );
+in
+pkgs.testers.nixosTest {
+  name = "ajail-seccomp-profile";
+
+  nodes.machine =
+    { ... }:
+    {
+      imports = [ common.machineConfig ];
+      environment.systemPackages = [
+        pkgs.python3
+        (common.mkMockClaude ''
+          # --- Profile rules are enforced ---
+          assert_denied "mkdir denied by profile"      mkdir /tmp/newdir
+          assert_denied "UNAME26 personality denied" \
+            python3 -c "import ctypes, sys; sys.exit(ctypes.CDLL(None).personality(0x20000) < 0)"
+          assert_ok     "PER_LINUX personality allowed" \
+            python3 -c "import ctypes, sys; sys.exit(ctypes.CDLL(None).personality(0) < 0)"
+          assert_ok     "file writes allowed"          touch /tmp/newfile
+
+          # --- Entries needing capabilities do not apply ---
+          assert_ok     "getpid allowed"               python3 -c "import os; os.getpid()"
+
+          # --- The built-in filter still applies alongside the profile ---
+          assert_denied "unix socket creation blocked" \
+            python3 -c "import socket; socket.socket(socket.AF_UNIX)"
+        '')
+      ];
+    };
+
+  testScript = common.setup + ''
+    machine.succeed("su - testuser -c 'mkdir -p ~/.config/ajail && cp ${config} ~/.config/ajail/config.json'")
+    output = machine.succeed("su - testuser -c 'cd ~/projects/myrepo && ajail 2>&1'")
+    assert "skipping seccomp profile entry for getpid, it requires CAP_SYS_ADMIN" in output, "Expected capability warning, got: " + output
+
+    # Profiles using features ajail cannot express are rejected
+    output = machine.fail("su - testuser -c 'cd ~/projects/myrepo && ajail --seccomp-profile ${unsupportedProfile} 2>&1'")
+    assert "unsupported action SCMP_ACT_TRACE" in output, "Expected profile error, got: " + output
+
+    output = machine.fail("su - testuser -c 'cd ~/projects/myrepo && ajail --seccomp-profile ${unknownArchProfile} 2>&1'")
+    assert "unknown architecture SCMP_ARCH_X86_46" in output, "Expected architecture error, got: " + output
+
+    output = machine.fail("su - testuser -c 'cd ~/projects/myrepo && ajail --seccomp-profile ${overlappingProfile} 2>&1'")
+    assert "syscall mkdir has entries with different actions" in output, "Expected overlap error, got: " + output
+  '';
+}
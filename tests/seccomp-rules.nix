@@ -0,0 +1,95 @@
+{ pkgs, common }:
+
+let
+  rulesConfig = pkgs.writeText "ajail-config.json" (
+    builtins.toJSON {
+      seccompRules = [
+        # Deny AF_INET6 sockets, matching only the int-sized domain argument
+        {
+          syscall = "socket";
+          args = [
+            {
+              index = 0;
+              op = "eq";
+              value = 10;
+              mask = 4294967295;
+            }
+          ];
+          action = {
+            errno = 97;
+          };
+        }
+        # Deny raising the nice value above 4
+        {
+          syscall = "setpriority";
+          args = [
+            {
+              index = 2;
+              op = "gt";
+              value = 4;
+            }
+          ];
+          action = {
+            errno = 13;
+          };
+        }
+        {
+          syscall = "sched_yield";
+          action = "kill";
+        }
+      ];
+    }
+  );
+
+  badConfig = pkgs.writeText "ajail-config.json" (
+    builtins.toJSON {
+      seccompRules = [
+        {
+          syscall = "no_such_syscall";
+          action = "kill";
+        }
+      ];
+    }
+  );
+in
+pkgs.testers.nixosTest {
+  name = "ajail-seccomp-rules";
+
+  nodes.machine =
+    { ... }:
+    {
+      imports = [ common.machineConfig ];
+      environment.systemPackages = [
+        pkgs.python3
+        (common.mkMockClaude ''
+          # --- Argument comparisons select which calls a rule matches ---
+          assert_denied "inet6 socket denied by config rule" \
+            python3 -c "import socket; socket.socket(socket.AF_INET6)"
+          assert_ok "inet socket still allowed" \
+            python3 -c "import socket; socket.socket(socket.AF_INET).close()"
+          assert_ok "nice 3 allowed" \
+            python3 -c "import os; os.setpriority(os.PRIO_PROCESS, 0, 3)"
+          assert_denied "nice 10 denied" \
+            python3 -c "import os; os.setpriority(os.PRIO_PROCESS, 0, 10)"
+
+          # --- Kill action terminates the process ---
+          assert_denied "sched_yield kills the process" \
+            python3 -c "import os; os.sched_yield()"
+
+          # --- Built-in unix socket rule still applies ---
+          assert_denied "unix socket creation blocked" \
+            python3 -c "import socket; socket.socket(socket.AF_UNIX)"
+        '')
+      ];
+    };
+
+  testScript = common.setup + ''
+    machine.succeed("su - testuser -c 'mkdir -p ~/.config/ajail && cp ${rulesConfig} ~/.config/ajail/config.json'")
+    machine.succeed("su - testuser -c 'cd ~/projects/myrepo && ajail'")
+
+    # Unknown syscall names are rejected before the sandbox starts
+    machine.succeed("su - testuser -c 'cp ${badConfig} ~/.config/ajail/config.json'")
+    output = machine.fail("su - testuser -c 'cd ~/projects/myrepo && ajail 2>&1'")
+    assert "unknown syscall 'no_such_syscall'" in output, "Expected rule error, got: " + output
+  '';
+}
@@ -0,0 +1,82 @@
+{ pkgs, common }:
+
+pkgs.testers.nixosTest {
+  name = "ajail-session-lifecycle";
+
+  nodes.machine =
+    { pkgs, ... }:
+    {
+      imports = [ common.machineConfig ];
+      environment.systemPackages = [
+        pkgs.util-linux
+        (pkgs.writeShellScriptBin "claude" ''
+          git config user.email "test@test.com"
+          git config user.name "Test"
+          echo "agent work" > agent.txt
+          git add agent.txt
+          git commit -q -m "agent change"
+          echo ready
+          sleep 60
+        '')
+      ];
+    };
+
+  testScript = ''
+    machine.wait_for_unit("multi-user.target")
+
+    machine.succeed("su - testuser -c 'git config --global user.email test@test.com'")
+    machine.succeed("su - testuser -c 'git config --global user.name Test'")
+    machine.succeed("su - testuser -c 'mkdir -p ~/projects/myrepo'")
+    machine.succeed("su - testuser -c 'cd ~/projects/myrepo && git init && git checkout -b main'")
+    machine.succeed("su - testuser -c 'cd ~/projects/myrepo && echo hello > file.txt && git add . && git commit -m initial'")
+    machine.succeed("su - testuser -c 'mkdir -p ~/.claude'")
+    machine.succeed("su - testuser -c 'echo {} > ~/.claude.json'")
+
+    sessions = "/home/testuser/.local/state/ajail/sessions"
+
+    def start_session(log):
+        machine.succeed(
+            "su - testuser -c 'cd ~/projects/myrepo && "
+            + "{ ajail --worktree --worktree-action discard > " + log + " 2>&1; echo exit $? >> " + log + "; } &'"
+        )
+        machine.wait_until_succeeds("grep -q ready " + log)
+        machine.succeed("ls " + sessions + "/*.json")
+
+    def branches():
+        return machine.succeed("su - testuser -c 'cd ~/projects/myrepo && git branch'")
+
+    # --- SIGTERM reaches the sandbox and the post-session phase still runs ---
+    start_session("/tmp/term.log")
+    machine.succeed("pkill -TERM -o -x ajail")
+    machine.wait_until_succeeds("grep -q '^exit' /tmp/term.log")
+    print(machine.succeed("cat /tmp/term.log"))
+    machine.succeed("grep -q 'Discarding worktree changes' /tmp/term.log")
+    assert "ajail-" not in branches(), "Expected the session branch to be removed"
+    machine.fail("ls " + sessions + "/*.json")
+    machine.fail("ls -d /tmp/ajail-*")
+
+    # --- A killed session is found by the next run ---
+    start_session("/tmp/kill.log")
+    machine.succeed("pkill -KILL -o -x ajail")
+    machine.wait_until_fails("pgrep -x sleep")
+    assert "ajail-" in branches(), "Expected the killed session's branch to remain"
+
+    # Without a terminal the session is kept for later
+    output = machine.succeed("su - testuser -c 'cd ~/projects/myrepo && ajail --command true < /dev/null 2>&1'")
+    print(output)
+    assert "did not finish cleaning up" in output, "Expected the orphaned session to be reported, got: " + output
+    assert "Run ajail on a terminal" in output, "Expected a hint, got: " + output
+    assert "ajail-" in branches(), "Expected the branch to be kept without a terminal"
+
+    # On a terminal, reviewing it offers the usual merge or discard
+    output = machine.succeed(
+      "su - testuser -c \"cd ~/projects/myrepo && printf 'y\\nm\\n' | "
+      + "script -qec 'ajail --command true' /dev/null\""
+    )
+    print(output)
+    assert "Merged worktree changes" in output, "Expected the orphaned session to be merged, got: " + output
+    machine.succeed("su - testuser -c 'test -f ~/projects/myrepo/agent.txt'")
+    assert "ajail-" not in branches(), "Expected the branch to be removed after review"
+    machine.fail("ls " + sessions + "/*.json")
+  '';
+}
@@ -0,0 +1,58 @@
+{ pkgs, common }:
+
+pkgs.testers.nixosTest {
+  name = "ajail-sessions";
+
+  nodes.machine =
+    { ... }:
+    {
+      imports = [ common.machineConfig ];
+    };
+
+  testScript = ''
+    machine.wait_for_unit("multi-user.target")
+
+    machine.succeed("su - testuser -c 'git config --global user.email test@test.com'")
+    machine.succeed("su - testuser -c 'git config --global user.name Test'")
+    machine.succeed("su - testuser -c 'mkdir -p ~/projects/myrepo'")
+    machine.succeed("su - testuser -c 'cd ~/projects/myrepo && git init && git checkout -b main'")
+    machine.succeed("su - testuser -c 'cd ~/projects/myrepo && echo hello > file.txt && git add . && git commit -m initial'")
+
+    def sessions(args):
+        return machine.succeed("su - testuser -c 'cd ~/projects/myrepo && ajail sessions " + args + " 2>&1'")
+
+    # Leftovers of two sessions whose worktrees are gone: one with a
+    # commit, one without changes
+    machine.succeed("su - testuser -c 'cd ~/projects/myrepo && git branch ajail-0000aaaa && git branch ajail-0000bbbb'")
+    machine.succeed("su - testuser -c 'cd ~/projects/myrepo && git worktree add /tmp/wt ajail-0000aaaa'")
+    machine.succeed("su - testuser -c 'cd /tmp/wt && echo work > agent.txt && git add agent.txt && git commit -m \"agent work\"'")
+    machine.succeed("rm -rf /tmp/wt")
+
+    # --- list finds sessions from their branches ---
+    output = sessions("list")
+    print(output)
+    assert "0000aaaa" in output and "0000bbbb" in output, "Expected both sessions, got: " + output
+    assert "branch: ajail-0000aaaa" in output, "Expected the branch location, got: " + output
+
+    # --- show lists the session's commits and files ---
+    output = sessions("show 0000aaaa")
+    print(output)
+    assert "agent work" in output and "agent.txt" in output, "Expected the session's changes, got: " + output
+
+    # --- clean removes only sessions without changes ---
+    output = sessions("clean")
+    print(output)
+    assert "Removed session 0000bbbb" in output, "Expected the empty session to be removed, got: " + output
+    assert "Keeping session 0000aaaa" in output, "Expected the session with changes to be kept, got: " + output
+
+    # --- merge brings the work into the original branch ---
+    sessions("merge ajail-0000aaaa")
+    machine.succeed("su - testuser -c 'test -f ~/projects/myrepo/agent.txt'")
+    branches = machine.succeed("su - testuser -c 'cd ~/projects/myrepo && git branch'")
+    assert "ajail-" not in branches, "Expected no ajail- branches, got: " + branches
+    assert "No sessions" in sessions("list")
+
+    # --- Unknown sessions are an error ---
+    machine.fail("su - testuser -c 'cd ~/projects/myrepo && ajail sessions discard 0000cccc'")
+  '';
+}
@@ -0,0 +1,54 @@
+{ pkgs, common }:
+
+let
+  # Try a terminal ioctl on /dev/tty and print "ok" or the errno name.
+  # Usage: tty-ioctl sti|linux
+  ttyIoctl = pkgs.writeScriptBin "tty-ioctl" ''
+    #!${pkgs.python3}/bin/python3
+    import errno, fcntl, os, sys, termios
+
+    request, arg = {
+        "sti": (termios.TIOCSTI, b"x"),
+        "linux": (0x541C, b"\x06"),  # TIOCLINUX, TIOCL_GETSHIFTSTATE
+    }[sys.argv[1]]
+    fd = os.open("/dev/tty", os.O_RDWR)
+    try:
+        fcntl.ioctl(fd, request, arg)
+        print("ok")
+    except OSError as e:
+        print(errno.errorcode[e.errno])
+  '';
+in
+pkgs.testers.nixosTest {
+  name = "ajail-terminal-injection";
+
+  nodes.machine =
+    { ... }:
+    {
+      imports = [ common.machineConfig ];
+      # Kernels since 6.2 can disable TIOCSTI globally; keep it enabled so
+      # the test shows the filter closing it
+      boot.kernel.sysctl."dev.tty.legacy_tiocsti" = 1;
+      environment.systemPackages = [
+        ttyIoctl
+        pkgs.util-linux
+        (common.mkMockClaude ''
+          # --- Input injection into the shared terminal is denied ---
+          assert_ok "TIOCSTI denied"   sh -c '[ "$(tty-ioctl sti)" = EPERM ]'
+          assert_ok "TIOCLINUX denied" sh -c '[ "$(tty-ioctl linux)" = EPERM ]'
+
+          # --- Other terminal ioctls still work ---
+          assert_ok "terminal size readable" stty size
+        '')
+      ];
+    };
+
+  testScript = common.setup + ''
+    # Sanity check: outside the sandbox TIOCSTI injects into the terminal
+    output = machine.succeed("su - testuser -c \"script -qec 'tty-ioctl sti' /dev/null\"")
+    assert "ok" in output, "Expected TIOCSTI to work outside the sandbox, got: " + output
+
+    # Run ajail on a terminal, as it would be interactively
+    machine.succeed("su - testuser -c \"cd ~/projects/myrepo && script -qec ajail /dev/null\"")
+  '';
+}
@@ -0,0 +1,157 @@
+{ pkgs, common }:
+
+let
+  # i386 program: create a socket via socket(2) or socketcall(2).
+  # Usage: socket-i386 socket|socketcall unix|inet
+  socketI386 = pkgs.pkgsi686Linux.writeCBin "socket-i386" ''
+    #include <string.h>
+    #include <sys/socket.h>
+    #include <sys/syscall.h>
+    #include <unistd.h>
+
+    int main(int argc, char **argv) {
+      if (argc != 3) return 2;
+      long domain = strcmp(argv[2], "unix") == 0 ? AF_UNIX : AF_INET;
+      long fd;
+      if (strcmp(argv[1], "socketcall") == 0) {
+        long args[3] = { domain, SOCK_STREAM, 0 };
+        fd = syscall(SYS_socketcall, 1 /* SYS_SOCKET */, args);
+      } else {
+        fd = syscall(SYS_socket, domain, SOCK_STREAM, 0);
+      }
+      return fd < 0;
+    }
+  '';
+
+  # x86_64 program issuing socket(AF_UNIX) through the x32 ABI
+  socketX32 = pkgs.writeCBin "socket-x32" ''
+    #include <sys/socket.h>
+    #include <sys/syscall.h>
+    #include <unistd.h>
+
+    int main(void) {
+      return syscall(0x40000000 | SYS_socket, AF_UNIX, SOCK_STREAM, 0) < 0;
+    }
+  '';
+
+  # Tracer skipping its child's getppid by setting the syscall number to -1,
+  # as debuggers and strace's syscall injection do. The kernel runs the
+  # filter again on -1, which must not be mistaken for an x32 call.
+  skipSyscall = pkgs.writeCBin "skip-syscall" ''
+    #include <signal.h>
+    #include <sys/ptrace.h>
+    #include <sys/syscall.h>
+    #include <sys/user.h>
+    #include <sys/wait.h>
+    #include <unistd.h>
+
+    int main(void) {
+      pid_t child = fork();
+      if (child == 0) {
+        ptrace(PTRACE_TRACEME, 0, 0, 0);
+        raise(SIGSTOP);
+        _exit(syscall(SYS_getppid) == 42 ? 0 : 1);
+      }
+      int status;
+      struct user_regs_struct regs;
+      waitpid(child, &status, 0);
+      for (;;) {
+        ptrace(PTRACE_SYSCALL, child, 0, 0);
+        waitpid(child, &status, 0);
+        if (!WIFSTOPPED(status))
+          return 1;
+        ptrace(PTRACE_GETREGS, child, 0, &regs);
+        if (regs.orig_rax == SYS_getppid)
+          break;
+      }
+      regs.orig_rax = -1;
+      ptrace(PTRACE_SETREGS, child, 0, &regs);
+      ptrace(PTRACE_SYSCALL, child, 0, 0);
+      waitpid(child, &status, 0);
+      if (!WIFSTOPPED(status))
+        return 1;
+      ptrace(PTRACE_GETREGS, child, 0, &regs);
+      regs.rax = 42;
+      ptrace(PTRACE_SETREGS, child, 0, &regs);
+      ptrace(PTRACE_CONT, child, 0, 0);
+      waitpid(child, &status, 0);
+      return !(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+    }
+  '';
+
+  # Passes when the command is killed by SIGSYS (exit status 128 + 31)
+  assertKilled = ''
+    assert_killed() {
+      local desc="$1"; shift
+      "$@" 2>/dev/null
+      if [ $? -eq 159 ]; then
+        echo "OK: $desc"
+      else
+        echo "FAIL: $desc"
+        FAIL=1
+      fi
+    }
+  '';
+in
+{
+  deny = pkgs.testers.nixosTest {
+    name = "ajail-unix-sockets-compat-deny";
+
+    nodes.machine =
+      { ... }:
+      {
+        imports = [ common.machineConfig ];
+        environment.systemPackages = [
+          socketI386
+          socketX32
+          skipSyscall
+          (common.mkMockClaude (
+            assertKilled
+            + ''
+              # --- 32-bit syscalls are killed instead of bypassing the filter ---
+              assert_killed "i386 socket(AF_UNIX) killed"     socket-i386 socket unix
+              assert_killed "i386 socketcall(AF_UNIX) killed" socket-i386 socketcall unix
+              assert_killed "i386 socket(AF_INET) killed"     socket-i386 socket inet
+
+              # --- x32 ABI calls are killed too ---
+              assert_killed "x32 socket(AF_UNIX) killed"      socket-x32
+
+              # --- A syscall skipped by a tracer is not mistaken for x32 ---
+              assert_ok     "tracer can skip a syscall"       skip-syscall
+            ''
+          ))
+        ];
+      };
+
+    testScript = common.setup + ''
+      # Sanity check: outside the sandbox the i386 binary can create unix sockets
+      machine.succeed("socket-i386 socket unix")
+      machine.succeed("socket-i386 socketcall unix")
+
+      machine.succeed("su - testuser -c 'cd ~/projects/myrepo && ajail'")
+    '';
+  };
+
+  allow = pkgs.testers.nixosTest {
+    name = "ajail-unix-sockets-compat-allow";
+
+    nodes.machine =
+      { ... }:
+      {
+        imports = [ common.machineConfig ];
+        environment.systemPackages = [
+          socketI386
+          (common.mkMockClaude ''
+            # --- 32-bit syscalls run, but the unix socket rules still apply ---
+            assert_denied "i386 socket(AF_UNIX) blocked"     socket-i386 socket unix
+            assert_denied "i386 socketcall(AF_UNIX) blocked" socket-i386 socketcall unix
+            assert_ok     "i386 socket(AF_INET) allowed"     socket-i386 socket inet
+          '')
+        ];
+      };
+
+    testScript = common.setup + ''
+      machine.succeed("su - testuser -c 'cd ~/projects/myrepo && ajail --allow-compat-syscalls'")
+    '';
+  };
+}
@@ -1,5 +1,11 @@
 { pkgs, common }:
 
+let
+  # io_uring_setup(4, &params); the syscall number is 425 on x86_64 and aarch64
+  ioUringSetup = ''
+    python3 -c "import ctypes, sys; p = ctypes.create_string_buffer(120); sys.exit(ctypes.CDLL(None).syscall(425, 4, p) < 0)"
+  '';
+in
 {
   allow = pkgs.testers.nixosTest {
     name = "ajail-unix-sockets-allow";
@@ -18,6 +24,9 @@
             # --- TCP sockets should also work ---
             assert_ok "tcp socket creation allowed" \
               python3 -c "import socket; s = socket.socket(socket.AF_INET, socket.SOCK_STREAM); s.close()"
+
+            # --- io_uring stays denied unless allowed separately ---
+            assert_denied "io_uring denied" ${ioUringSetup}
           '')
         ];
       };
@@ -45,6 +54,9 @@
             assert_ok "tcp socket creation allowed" \
               python3 -c "import socket; s = socket.socket(socket.AF_INET, socket.SOCK_STREAM); s.close()"
 
+            # --- io_uring is denied so it cannot create sockets either ---
+            assert_denied "io_uring denied" ${ioUringSetup}
+
             # --- Regular file I/O should still work ---
             assert_ok "file I/O works" \
               python3 -c "open('/tmp/test-file', 'w').write('hello')"
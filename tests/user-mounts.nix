@@ -0,0 +1,54 @@
+{ pkgs, common }:
+
+pkgs.testers.nixosTest {
+  name = "ajail-user-mounts";
+
+  nodes.machine =
+    { ... }:
+    {
+      imports = [ common.machineConfig ];
+      environment.systemPackages = [
+        (common.mkMockClaude ''
+          # --- Read-write bind mount under $HOME ---
+          assert_ok "can read rw bind"  cat ~/dataset/data.txt
+          assert_ok "can write rw bind" bash -c 'echo sandbox > ~/dataset/written.txt'
+
+          # --- Read-only bind mount at a different destination ---
+          assert_ok     "can read ro bind"     cat ~/sibling/README
+          assert_denied "cannot write ro bind" bash -c 'echo x >> ~/sibling/README'
+
+          # --- Bind mount source under /tmp survives the /tmp overlay ---
+          assert_ok "can read /tmp bind" cat /tmp/shared/data.txt
+          assert_not_exists "host /tmp still hidden" /tmp/host-marker
+
+          # --- Extra tmpfs is empty and writable ---
+          assert_ok "can write tmpfs" bash -c 'echo x > /home/testuser/scratch/file'
+
+          # --- Hidden paths are masked ---
+          assert_not_exists "hidden dir is empty"  ~/projects/myrepo/private/secret
+          assert_denied     "hidden file is empty" grep -q secret ~/projects/myrepo/private.key
+        '')
+      ];
+    };
+
+  testScript = common.setup + ''
+    machine.succeed("su - testuser -c 'mkdir -p ~/dataset && echo data > ~/dataset/data.txt'")
+    machine.succeed("su - testuser -c 'mkdir -p ~/projects/sibling && echo readme > ~/projects/sibling/README'")
+    machine.succeed("su - testuser -c 'mkdir -p /tmp/shared && echo data > /tmp/shared/data.txt'")
+    machine.succeed("su - testuser -c 'mkdir -p ~/projects/myrepo/private && echo secret > ~/projects/myrepo/private/secret'")
+    machine.succeed("su - testuser -c 'echo secret > ~/projects/myrepo/private.key'")
+
+    machine.succeed(
+        "su - testuser -c 'cd ~/projects/myrepo && ajail"
+        + " --bind ~/dataset"
+        + " --ro-bind ~/projects/sibling:~/sibling"
+        + " --ro-bind /tmp/shared"
+        + " --tmpfs ~/scratch"
+        + " --hide ~/projects/myrepo/private"
+        + " --hide ~/projects/myrepo/private.key'"
+    )
+
+    # Writes through the rw bind reach the host
+    machine.succeed("su - testuser -c 'grep sandbox ~/dataset/written.txt'")
+  '';
+}
@@ -0,0 +1,86 @@
+{ pkgs, common }:
+
+pkgs.testers.nixosTest {
+  name = "ajail-worktree-actions";
+
+  nodes.machine =
+    { pkgs, ... }:
+    {
+      imports = [ common.machineConfig ];
+      environment.systemPackages = [
+        (pkgs.writeShellScriptBin "claude" ''
+          git config user.email "test@test.com"
+          git config user.name "Test"
+          echo "$RUN" > first-$RUN.txt
+          git add first-$RUN.txt
+          git commit -m "first $RUN"
+          echo "$RUN" > second-$RUN.txt
+          git add second-$RUN.txt
+          git commit -m "second $RUN"
+          echo "$RUN" > uncommitted-$RUN.txt
+        '')
+      ];
+    };
+
+  testScript = ''
+    machine.wait_for_unit("multi-user.target")
+
+    machine.succeed("su - testuser -c 'git config --global user.email test@test.com'")
+    machine.succeed("su - testuser -c 'git config --global user.name Test'")
+    machine.succeed("su - testuser -c 'mkdir -p ~/projects/myrepo'")
+    machine.succeed("su - testuser -c 'cd ~/projects/myrepo && git init && git checkout -b main'")
+    machine.succeed("su - testuser -c 'cd ~/projects/myrepo && echo hello > file.txt && git add . && git commit -m initial'")
+    machine.succeed("su - testuser -c 'mkdir -p ~/.claude'")
+    machine.succeed("su - testuser -c 'echo {} > ~/.claude.json'")
+
+    def repo(cmd):
+        return machine.succeed("su - testuser -c 'cd ~/projects/myrepo && " + cmd + "'")
+
+    def run(name, args):
+        output = repo("RUN=" + name + " ajail --worktree " + args + " 2>&1")
+        print(output)
+        return output
+
+    # --- squash: one commit on top of main ---
+    head = repo("git rev-parse HEAD").strip()
+    run("squash", "--worktree-action squash")
+    assert repo("git rev-parse HEAD~1").strip() == head, "Expected a single squashed commit"
+    body = repo("git log --format=%B -1")
+    assert "first squash" in body and "second squash" in body, "Expected the squashed commits listed, got: " + body
+    repo("test -f first-squash.txt && test -f uncommitted-squash.txt")
+
+    # --- rebase: the commits land on main without a merge commit ---
+    run("rebase", "--worktree-action rebase")
+    merges = repo("git log --merges --oneline")
+    assert merges.strip() == "", "Expected a linear history, got: " + merges
+    repo("test -f second-rebase.txt && test -f uncommitted-rebase.txt")
+
+    # --- branch: the branch is kept under a new name, the worktree removed ---
+    head = repo("git rev-parse HEAD").strip()
+    output = run("branch", "--worktree-action branch --branch-name agent/feature")
+    assert "Kept the worktree changes on branch agent/feature" in output, "Expected the branch to be kept, got: " + output
+    repo("git show agent/feature:uncommitted-branch.txt")
+    assert repo("git rev-parse HEAD").strip() == head, "Expected main to be unchanged"
+    worktrees = repo("git worktree list")
+    assert len(worktrees.strip().split("\n")) == 1, "Expected the worktree to be removed, got: " + worktrees
+
+    # --- patch: a format-patch series, or a bundle ---
+    run("patch", "--worktree-action patch --patch-output /tmp/series")
+    machine.succeed("ls /tmp/series/0001-first-patch.patch /tmp/series/0003-*.patch")
+    run("bundle", "--worktree-action patch --patch-output /tmp/work.bundle")
+    repo("git bundle verify /tmp/work.bundle")
+    assert repo("git rev-parse HEAD").strip() == head, "Expected main to be unchanged"
+
+    # --- keep: worktree and branch stay, and are not offered on the next run ---
+    output = run("keep", "--worktree-action keep")
+    assert "Kept the worktree at" in output, "Expected the worktree to be kept, got: " + output
+    machine.succeed("ls -d /tmp/ajail-worktree-*")
+    output = repo("ajail sessions list 2>&1")
+    assert "kept" in output, "Expected the session listed as kept, got: " + output
+    output = repo("ajail --command true < /dev/null 2>&1")
+    assert "did not finish cleaning up" not in output, "Expected the kept session not to be offered, got: " + output
+
+    branches = repo("git branch")
+    assert branches.count("ajail-") == 1, "Expected only the kept session branch, got: " + branches
+  '';
+}
@@ -0,0 +1,52 @@
+{ pkgs, common }:
+
+pkgs.testers.nixosTest {
+  name = "ajail-worktree-carry";
+
+  nodes.machine =
+    { ... }:
+    {
+      imports = [ common.machineConfig ];
+      environment.systemPackages = [
+        (common.mkMockClaude ''
+          if [ -n "$EXPECT_CARRIED" ]; then
+            # --- Staged, unstaged and untracked changes are carried over ---
+            assert_ok "staged change kept staged"  sh -c 'git diff --cached --name-only | grep -qx staged.txt'
+            assert_ok "unstaged change kept"       sh -c 'git diff --name-only | grep -qx tracked.txt'
+            assert_ok "untracked file copied"      test -f untracked.txt
+            assert_denied "ignored file left out"  test -e ignored.log
+          else
+            assert_denied "untracked file not copied" test -f untracked.txt
+          fi
+          echo "agent" > agent.txt
+        '')
+      ];
+    };
+
+  testScript = common.setup + ''
+    def repo(cmd):
+        return machine.succeed("su - testuser -c 'cd ~/projects/myrepo && " + cmd + "'")
+
+    repo("git config user.email test@test.com && git config user.name Test")
+    repo("echo base > staged.txt && echo base > tracked.txt && echo ignored.log > .gitignore")
+    repo("git add . && git commit -m base")
+    repo("echo staged >> staged.txt && git add staged.txt")
+    repo("echo unstaged >> tracked.txt && echo new > untracked.txt && echo log > ignored.log")
+    before = repo("git status --short")
+
+    # --- --carry-changes all: the review shows only the agent's work ---
+    output = repo("EXPECT_CARRIED=1 ajail --worktree --carry-changes all --worktree-action discard 2>&1")
+    print(output)
+    assert "FAIL" not in output, "Mock reported a failure: " + output
+    assert "agent.txt" in output, "Expected the agent's file in the review, got: " + output
+    for name in ["staged.txt", "tracked.txt", "untracked.txt"]:
+        assert name not in output, "Expected carried-over " + name + " to be left out of the review, got: " + output
+
+    # The original checkout is untouched
+    assert repo("git status --short") == before, "Expected the original checkout to be unchanged"
+
+    # --- Without it, the worktree starts from HEAD ---
+    output = repo("ajail --worktree --worktree-action discard 2>&1")
+    assert "FAIL" not in output, "Mock reported a failure: " + output
+  '';
+}
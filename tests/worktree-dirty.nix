@@ -0,0 +1,62 @@
+{ pkgs, common }:
+
+pkgs.testers.nixosTest {
+  name = "ajail-worktree-dirty";
+
+  nodes.machine =
+    { pkgs, ... }:
+    {
+      imports = [ common.machineConfig ];
+      environment.systemPackages = [
+        (pkgs.writeShellScriptBin "claude" ''
+          git config user.email "test@test.com"
+          git config user.name "Test"
+          echo "$RUN" > agent-$RUN.txt
+          git add agent-$RUN.txt
+          git commit -m "agent change $RUN"
+        '')
+      ];
+    };
+
+  testScript = ''
+    machine.wait_for_unit("multi-user.target")
+
+    machine.succeed("su - testuser -c 'git config --global user.email test@test.com'")
+    machine.succeed("su - testuser -c 'git config --global user.name Test'")
+    machine.succeed("su - testuser -c 'mkdir -p ~/projects/myrepo'")
+    machine.succeed("su - testuser -c 'cd ~/projects/myrepo && git init && git checkout -b main'")
+    machine.succeed("su - testuser -c 'cd ~/projects/myrepo && echo hello > file.txt && git add . && git commit -m initial'")
+    machine.succeed("su - testuser -c 'mkdir -p ~/.claude'")
+    machine.succeed("su - testuser -c 'echo {} > ~/.claude.json'")
+
+    def repo(cmd):
+        return machine.succeed("su - testuser -c 'cd ~/projects/myrepo && " + cmd + "'")
+
+    # The user keeps editing the original checkout during the session
+    repo("echo edit >> file.txt")
+
+    # --- By default, merging into a dirty checkout is refused ---
+    output = repo("RUN=one ajail --worktree --worktree-action merge 2>&1")
+    print(output)
+    assert "has uncommitted changes, not merging" in output, "Expected the merge to be refused, got: " + output
+    repo("test ! -f agent-one.txt && grep -q edit file.txt")
+    branch = repo("git branch --list \"ajail-*\" --format=\"%(refname:short)\"").strip()
+    assert branch.startswith("ajail-"), "Expected the session branch to be kept, got: " + branch
+
+    # --- The kept session merges with the changes stashed ---
+    output = repo("ajail --dirty-checkout stash sessions merge " + branch + " 2>&1")
+    print(output)
+    assert "Re-applied the stashed changes" in output, "Expected the stash to be re-applied, got: " + output
+    repo("test -f agent-one.txt && grep -q edit file.txt")
+    assert repo("git stash list").strip() == "", "Expected an empty stash"
+
+    # --- no-checkout moves the branch but leaves the files alone ---
+    output = repo("RUN=two ajail --worktree --worktree-action merge --dirty-checkout no-checkout 2>&1")
+    print(output)
+    assert "agent change two" in repo("git log --oneline -1"), "Expected the branch to be merged"
+    repo("test ! -f agent-two.txt && grep -q edit file.txt")
+
+    branches = repo("git branch")
+    assert "ajail-" not in branches, "Expected no ajail- branches, got: " + branches
+  '';
+}
@@ -0,0 +1,76 @@
+{ pkgs, common }:
+
+pkgs.testers.nixosTest {
+  name = "ajail-worktree-restore";
+
+  nodes.machine =
+    { pkgs, ... }:
+    {
+      imports = [ common.machineConfig ];
+      environment.systemPackages = [
+        (pkgs.writeShellScriptBin "claude" ''
+          git config user.email "test@test.com"
+          git config user.name "Test"
+          echo "$RUN" > committed-$RUN.txt
+          git add committed-$RUN.txt
+          git commit -m "committed change $RUN"
+          echo "$RUN" > uncommitted-$RUN.txt
+        '')
+      ];
+    };
+
+  testScript = ''
+    machine.wait_for_unit("multi-user.target")
+
+    machine.succeed("su - testuser -c 'git config --global user.email test@test.com'")
+    machine.succeed("su - testuser -c 'git config --global user.name Test'")
+    machine.succeed("su - testuser -c 'mkdir -p ~/projects/myrepo'")
+    machine.succeed("su - testuser -c 'cd ~/projects/myrepo && git init && git checkout -b main'")
+    machine.succeed("su - testuser -c 'cd ~/projects/myrepo && echo hello > file.txt && git add . && git commit -m initial'")
+    machine.succeed("su - testuser -c 'mkdir -p ~/.claude'")
+    machine.succeed("su - testuser -c 'echo {} > ~/.claude.json'")
+
+    def repo(cmd):
+        return machine.succeed("su - testuser -c 'cd ~/projects/myrepo && " + cmd + "'")
+
+    def discarded():
+        return repo("git for-each-ref --format=%\\(refname\\) refs/ajail/discarded").split()
+
+    # --- Discarding saves the work, uncommitted changes included ---
+    output = repo("RUN=one ajail --worktree --worktree-action discard 2>&1")
+    print(output)
+    refs = discarded()
+    assert len(refs) == 1, "Expected one discarded session, got: " + str(refs)
+    session_id = refs[0].split("/")[-1]
+    assert "ajail sessions restore " + session_id in output, "Expected a restore hint, got: " + output
+    assert "ajail-" not in repo("git branch"), "Expected the session branch to be removed"
+    repo("git show " + refs[0] + ":uncommitted-one.txt")
+
+    output = repo("ajail sessions list 2>&1")
+    print(output)
+    assert session_id + "  discarded" in output, "Expected the discarded session listed, got: " + output
+
+    # --- restore brings the branch back, ready to merge ---
+    output = repo("ajail sessions restore " + session_id + " 2>&1")
+    print(output)
+    assert discarded() == [], "Expected the discarded ref to be removed"
+    repo("ajail sessions merge " + session_id)
+    repo("test -f committed-one.txt && test -f uncommitted-one.txt")
+
+    # Unknown ids are an error
+    machine.fail("su - testuser -c 'cd ~/projects/myrepo && ajail sessions restore 0000cccc'")
+
+    # --- Discarded sessions expire ---
+    repo("RUN=two ajail --worktree --worktree-action discard")
+    assert len(discarded()) == 1, "Expected the second session to be saved"
+    repo("ajail sessions clean")
+    assert len(discarded()) == 1, "Expected the session to be kept within the retention"
+    output = repo("ajail --discard-retention-days 0 sessions clean 2>&1")
+    assert "Removed discarded session" in output, "Expected the session to expire, got: " + output
+    assert discarded() == [], "Expected no discarded sessions left"
+
+    # With no retention, nothing is saved
+    repo("RUN=three ajail --worktree --worktree-action discard --discard-retention-days 0")
+    assert discarded() == [], "Expected nothing saved without retention"
+  '';
+}
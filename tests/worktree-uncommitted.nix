@@ -0,0 +1,62 @@
+{ pkgs, common }:
+
+pkgs.testers.nixosTest {
+  name = "ajail-worktree-uncommitted";
+
+  nodes.machine =
+    { pkgs, ... }:
+    {
+      imports = [ common.machineConfig ];
+      environment.systemPackages = [
+        (pkgs.writeShellScriptBin "claude" ''
+          git config user.email "test@test.com"
+          git config user.name "Test"
+          echo "$RUN" > committed-$RUN.txt
+          git add committed-$RUN.txt
+          git commit -m "committed change $RUN"
+          # Left uncommitted: an edit and a new file
+          echo "$RUN" >> file.txt
+          echo "$RUN" > untracked-$RUN.txt
+        '')
+      ];
+    };
+
+  testScript = ''
+    machine.wait_for_unit("multi-user.target")
+
+    machine.succeed("su - testuser -c 'git config --global user.email test@test.com'")
+    machine.succeed("su - testuser -c 'git config --global user.name Test'")
+    machine.succeed("su - testuser -c 'mkdir -p ~/projects/myrepo'")
+    machine.succeed("su - testuser -c 'cd ~/projects/myrepo && git init && git checkout -b main'")
+    machine.succeed("su - testuser -c 'cd ~/projects/myrepo && echo hello > file.txt && git add . && git commit -m initial'")
+    machine.succeed("su - testuser -c 'mkdir -p ~/.claude'")
+    machine.succeed("su - testuser -c 'echo {} > ~/.claude.json'")
+
+    def repo(cmd):
+        return machine.succeed("su - testuser -c 'cd ~/projects/myrepo && " + cmd + "'")
+
+    # --- By default, merging commits them on the session branch first ---
+    output = repo("RUN=one ajail --worktree --worktree-action merge 2>&1")
+    print(output)
+    assert "Committed uncommitted worktree changes" in output, "Expected the changes to be committed, got: " + output
+    log = repo("git log --format=%B -2")
+    assert "uncommitted changes from session" in log, "Expected a commit of the uncommitted changes, got: " + log
+    assert "Ajail-Session: " in log, "Expected the commit to name the session, got: " + log
+    repo("test -f untracked-one.txt && grep -q one file.txt")
+    status = repo("git status --short")
+    assert status.strip() == "", "Expected a clean checkout, got: " + status
+
+    # --- With carry, they are left uncommitted in the original checkout ---
+    output = repo("RUN=two ajail --worktree --worktree-action merge --merge-uncommitted carry 2>&1")
+    print(output)
+    assert "Carried uncommitted worktree changes" in output, "Expected the changes to be carried over, got: " + output
+    log = repo("git log --oneline -1")
+    assert "committed change two" in log, "Expected the committed work to be merged, got: " + log
+    status = repo("git status --short")
+    assert " M file.txt" in status, "Expected file.txt modified, got: " + status
+    assert "?? untracked-two.txt" in status, "Expected untracked-two.txt untracked, got: " + status
+
+    branches = repo("git branch")
+    assert "ajail-" not in branches, "Expected no ajail- branches, got: " + branches
+  '';
+}